@@ -0,0 +1,49 @@
+use std::env;
+use std::str::FromStr;
+
+/// Runtime settings read from the environment at startup
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Number of renders allowed to run FFmpeg at the same time
+    pub render_workers: usize,
+    /// Maximum number of renders waiting for a worker before new requests are rejected
+    pub render_queue_capacity: usize,
+    /// Hours to keep uploaded frames that were never rendered or whose render was cancelled
+    pub upload_ttl_hours: u64,
+    /// Hours to keep completed jobs and their outputs
+    pub completed_job_ttl_hours: u64,
+    /// Hours to keep failed jobs
+    pub failed_job_ttl_hours: u64,
+    /// Minutes between reaper sweeps
+    pub reaper_interval_minutes: u64,
+    /// Maximum number of entries read from an uploaded frame archive
+    pub archive_max_entries: usize,
+    /// Maximum total size of the frames extracted from one archive, in GB
+    pub archive_max_extracted_gb: u64,
+    /// Maximum size of one frame sent as a resumable upload, in GB
+    pub upload_max_file_gb: u64,
+}
+
+impl Config {
+    pub fn from_env() -> Self {
+        Self {
+            render_workers: env_or("RENDER_WORKERS", 1).max(1),
+            render_queue_capacity: env_or("RENDER_QUEUE_CAPACITY", 20),
+            upload_ttl_hours: env_or("UPLOAD_TTL_HOURS", 24),
+            completed_job_ttl_hours: env_or("COMPLETED_JOB_TTL_HOURS", 24),
+            failed_job_ttl_hours: env_or("FAILED_JOB_TTL_HOURS", 12),
+            reaper_interval_minutes: env_or("REAPER_INTERVAL_MINUTES", 15).max(1),
+            archive_max_entries: env_or("ARCHIVE_MAX_ENTRIES", 100_000),
+            archive_max_extracted_gb: env_or("ARCHIVE_MAX_EXTRACTED_GB", 20),
+            upload_max_file_gb: env_or("UPLOAD_MAX_FILE_GB", 10),
+        }
+    }
+}
+
+/// Parse an environment variable, falling back to the default if unset or invalid
+fn env_or<T: FromStr>(key: &str, default: T) -> T {
+    env::var(key)
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(default)
+}
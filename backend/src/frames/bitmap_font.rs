@@ -0,0 +1,69 @@
+use image::{Rgb, RgbImage};
+
+/// Glyph width and height in font pixels
+const GLYPH_WIDTH: u32 = 5;
+pub const GLYPH_HEIGHT: u32 = 7;
+
+/// Horizontal distance between the starts of neighbouring glyphs
+const ADVANCE: u32 = GLYPH_WIDTH + 1;
+
+/// Rows of a 5x7 glyph, top to bottom, with the leftmost pixel in bit 4.
+/// Only the characters of frame numbers and timestamps are covered; anything
+/// else is drawn as a blank.
+fn glyph(c: char) -> [u8; 7] {
+    match c {
+        '0' => [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E],
+        '1' => [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        '2' => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F],
+        '3' => [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E],
+        '4' => [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02],
+        '5' => [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E],
+        '6' => [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E],
+        '7' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+        '8' => [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E],
+        '9' => [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C],
+        '#' => [0x0A, 0x0A, 0x1F, 0x0A, 0x1F, 0x0A, 0x0A],
+        '-' => [0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00],
+        ':' => [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x0C, 0x00],
+        '.' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C],
+        _ => [0; 7],
+    }
+}
+
+/// Width in pixels of `text` drawn at `scale`
+pub fn text_width(text: &str, scale: u32) -> u32 {
+    let count = text.chars().count() as u32;
+    if count == 0 {
+        0
+    } else {
+        (count * ADVANCE - 1) * scale
+    }
+}
+
+/// Draw `text` with its top-left corner at (`x`, `y`), each font pixel a
+/// `scale`-pixel square. Glyphs are clipped to `max_x` and the image bounds.
+pub fn draw_text(image: &mut RgbImage, x: u32, y: u32, max_x: u32, text: &str, scale: u32, color: Rgb<u8>) {
+    let max_x = max_x.min(image.width());
+    for (i, c) in text.chars().enumerate() {
+        let left = x + i as u32 * ADVANCE * scale;
+        if left >= max_x {
+            break;
+        }
+        for (row, bits) in glyph(c).iter().enumerate() {
+            for col in 0..GLYPH_WIDTH {
+                if bits & (0x10 >> col) == 0 {
+                    continue;
+                }
+                for dy in 0..scale {
+                    for dx in 0..scale {
+                        let px = left + col * scale + dx;
+                        let py = y + row as u32 * scale + dy;
+                        if px < max_x && py < image.height() {
+                            image.put_pixel(px, py, color);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
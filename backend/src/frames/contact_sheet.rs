@@ -0,0 +1,205 @@
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use anyhow::{Result, Context};
+use chrono::NaiveDateTime;
+use image::{imageops, DynamicImage, ImageReader, Rgb, RgbImage};
+use crate::frames::bitmap_font::{draw_text, text_width, GLYPH_HEIGHT};
+use crate::frames::metadata::{capture_time, modified_time};
+use crate::frames::thumbnail::{save, thumbnail, ThumbnailFormat, ThumbnailSpec};
+
+/// Most columns or rows of a contact sheet
+const MAX_GRID_SIDE: u32 = 20;
+
+/// Narrowest and widest tile
+const MIN_TILE_WIDTH: u32 = 32;
+const MAX_TILE_WIDTH: u32 = 1024;
+
+/// Longest side of a whole contact sheet, not counting gaps and labels
+const MAX_SHEET_SIDE: u32 = 8192;
+
+/// Space around and between tiles
+const GAP: u32 = 4;
+
+/// Largest label font scale
+const MAX_FONT_SCALE: u32 = 4;
+
+const BACKGROUND: Rgb<u8> = Rgb([24, 24, 24]);
+const TEXT_COLOR: Rgb<u8> = Rgb([230, 230, 230]);
+
+/// Timestamp label formats, longest first; tiles too narrow for the full date
+/// and time get a shorter one
+const TIMESTAMP_FORMATS: [&str; 3] = ["%Y-%m-%d %H:%M:%S", "%m-%d %H:%M:%S", "%H:%M:%S"];
+
+/// Grid, tile size and format of a contact sheet
+#[derive(Debug, Clone, Copy)]
+pub struct ContactSheetSpec {
+    pub columns: u32,
+    pub rows: u32,
+    pub tile_width: u32,
+    pub format: ThumbnailFormat,
+}
+
+impl ContactSheetSpec {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(1..=MAX_GRID_SIDE).contains(&self.columns) || !(1..=MAX_GRID_SIDE).contains(&self.rows) {
+            return Err(format!("Columns and rows must be between 1 and {}", MAX_GRID_SIDE));
+        }
+        if !(MIN_TILE_WIDTH..=MAX_TILE_WIDTH).contains(&self.tile_width) {
+            return Err(format!(
+                "Tile width must be between {} and {}",
+                MIN_TILE_WIDTH, MAX_TILE_WIDTH
+            ));
+        }
+        if self.columns.max(self.rows) * self.tile_width > MAX_SHEET_SIDE {
+            return Err(format!(
+                "Columns and rows times the tile width must be at most {}",
+                MAX_SHEET_SIDE
+            ));
+        }
+        Ok(())
+    }
+
+    /// Cache file name for a sheet of `frames`. The frame list is hashed so
+    /// a different order or set of frames gets its own file.
+    fn file_name(&self, frames: &[(usize, String)]) -> String {
+        let mut hasher = DefaultHasher::new();
+        frames.hash(&mut hasher);
+        format!(
+            "{}x{}.{}.{:016x}.{}",
+            self.columns,
+            self.rows,
+            self.tile_width,
+            hasher.finish(),
+            self.format.extension()
+        )
+    }
+
+    /// Largest font scale at which a full timestamp fits the tile
+    fn font_scale(&self) -> u32 {
+        // Every digit is as wide as any other, so any time will do
+        let full = NaiveDateTime::default().format(TIMESTAMP_FORMATS[0]).to_string();
+        (1..=MAX_FONT_SCALE)
+            .rev()
+            .find(|scale| text_width(&full, *scale) <= self.tile_width)
+            .unwrap_or(1)
+    }
+}
+
+/// The longest timestamp label that fits `width` at `scale`
+fn timestamp_label(taken: &NaiveDateTime, width: u32, scale: u32) -> String {
+    TIMESTAMP_FORMATS
+        .iter()
+        .map(|format| taken.format(format).to_string())
+        .find(|label| text_width(label, scale) <= width)
+        .unwrap_or_else(|| taken.format(TIMESTAMP_FORMATS[2]).to_string())
+}
+
+/// Up to `count` frames spread evenly from the first to the last, with their
+/// indexes
+fn evenly_spaced(files: &[String], count: usize) -> Vec<(usize, String)> {
+    if files.len() <= count {
+        return files.iter().cloned().enumerate().collect();
+    }
+    if count == 1 {
+        return vec![(0, files[0].clone())];
+    }
+
+    let last = files.len() - 1;
+    (0..count)
+        .map(|i| {
+            let index = (i * last + (count - 1) / 2) / (count - 1);
+            (index, files[index].clone())
+        })
+        .collect()
+}
+
+/// Whether a cached sheet is at least as new as every frame on it
+fn is_fresh(sheet: &Path, frame_paths: &[PathBuf]) -> bool {
+    let modified = |path: &Path| fs::metadata(path).and_then(|m| m.modified()).ok();
+    let Some(sheet_modified) = modified(sheet) else {
+        return false;
+    };
+    frame_paths
+        .iter()
+        .all(|frame| modified(frame).is_some_and(|frame_modified| frame_modified <= sheet_modified))
+}
+
+/// Path of a contact sheet of `files`, the job's frames in preview order,
+/// generating it into `cache_dir` unless an up-to-date copy is already there.
+///
+/// Tiles are `spec.tile_width` wide and as tall as the first frame's aspect
+/// ratio makes them, up to square. Each is labelled with the frame's preview
+/// index and its capture time, or its modification time without EXIF data.
+/// Tiles reuse the thumbnail cache in `thumbnails_dir`.
+pub fn contact_sheet(
+    frames_dir: &Path,
+    files: &[String],
+    thumbnails_dir: &Path,
+    cache_dir: &Path,
+    spec: &ContactSheetSpec,
+) -> Result<PathBuf> {
+    let frames = evenly_spaced(files, (spec.columns * spec.rows) as usize);
+    let Some((_, first)) = frames.first() else {
+        anyhow::bail!("Job has no frames");
+    };
+
+    let frame_paths: Vec<PathBuf> = frames.iter().map(|(_, f)| frames_dir.join(f)).collect();
+    let path = cache_dir.join(spec.file_name(&frames));
+    if is_fresh(&path, &frame_paths) {
+        return Ok(path);
+    }
+
+    let (width, height) = image::image_dimensions(frames_dir.join(first))
+        .with_context(|| format!("Failed to read the size of {}", first))?;
+    let tile_width = spec.tile_width;
+    let tile_height = ((tile_width as u64 * height as u64) / width.max(1) as u64).clamp(1, tile_width as u64) as u32;
+
+    let scale = spec.font_scale();
+    let line_height = GLYPH_HEIGHT * scale;
+    let label_height = GAP + line_height * 2 + scale * 2;
+
+    let columns = spec.columns.min(frames.len() as u32);
+    let rows = (frames.len() as u32).div_ceil(columns);
+    let cell_height = tile_height + label_height;
+    let mut sheet = RgbImage::from_pixel(
+        columns * (tile_width + GAP) + GAP,
+        rows * (cell_height + GAP) + GAP,
+        BACKGROUND,
+    );
+
+    let tile_spec = ThumbnailSpec {
+        width: Some(tile_width),
+        height: Some(tile_height),
+        format: ThumbnailFormat::Jpeg,
+    };
+    for (i, ((index, filename), frame_path)) in frames.iter().zip(&frame_paths).enumerate() {
+        let left = GAP + (i as u32 % columns) * (tile_width + GAP);
+        let top = GAP + (i as u32 / columns) * (cell_height + GAP);
+
+        let tile_path = thumbnail(frames_dir, filename, thumbnails_dir, &tile_spec)?;
+        let tile = ImageReader::open(&tile_path)?
+            .with_guessed_format()?
+            .decode()
+            .with_context(|| format!("Failed to decode the thumbnail of {}", filename))?
+            .to_rgb8();
+        // Frames of another shape than the first are centred in their tile
+        let x = left + (tile_width - tile.width().min(tile_width)) / 2;
+        let y = top + (tile_height - tile.height().min(tile_height)) / 2;
+        imageops::replace(&mut sheet, &tile, x as i64, y as i64);
+
+        let label_top = top + tile_height + GAP;
+        let right = left + tile_width;
+        draw_text(&mut sheet, left, label_top, right, &format!("#{}", index), scale, TEXT_COLOR);
+        let taken = capture_time(frame_path).or_else(|| modified_time(frame_path));
+        if let Some(taken) = taken {
+            let timestamp = timestamp_label(&taken, tile_width, scale);
+            draw_text(&mut sheet, left, label_top + line_height + scale * 2, right, &timestamp, scale, TEXT_COLOR);
+        }
+    }
+
+    save(&DynamicImage::ImageRgb8(sheet), spec.format, &path)?;
+
+    Ok(path)
+}
@@ -0,0 +1,44 @@
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::Path;
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime};
+use exif::{In, Tag, Value};
+
+/// When a frame was captured, from EXIF `DateTimeOriginal`.
+///
+/// Uses `SubSecTimeOriginal` when present so burst frames within one second keep
+/// their order. EXIF times are camera-local, so no time zone is attached.
+pub fn capture_time(path: &Path) -> Option<NaiveDateTime> {
+    let file = File::open(path).ok()?;
+    let exif = exif::Reader::new()
+        .read_from_container(&mut BufReader::new(file))
+        .ok()?;
+
+    let field = exif.get_field(Tag::DateTimeOriginal, In::PRIMARY)?;
+    let Value::Ascii(ref parts) = field.value else {
+        return None;
+    };
+    let mut datetime = exif::DateTime::from_ascii(parts.first()?).ok()?;
+
+    if let Some(subsec) = exif.get_field(Tag::SubSecTimeOriginal, In::PRIMARY) {
+        if let Value::Ascii(ref parts) = subsec.value {
+            if let Some(data) = parts.first() {
+                let _ = datetime.parse_subsec(data);
+            }
+        }
+    }
+
+    NaiveDate::from_ymd_opt(datetime.year as i32, datetime.month as u32, datetime.day as u32)?
+        .and_hms_nano_opt(
+            datetime.hour as u32,
+            datetime.minute as u32,
+            datetime.second as u32,
+            datetime.nanosecond.unwrap_or(0),
+        )
+}
+
+/// File modification time in the server's local time zone, comparable with EXIF times
+pub fn modified_time(path: &Path) -> Option<NaiveDateTime> {
+    let modified = fs::metadata(path).ok()?.modified().ok()?;
+    Some(DateTime::<Local>::from(modified).naive_local())
+}
@@ -0,0 +1,7 @@
+pub mod bitmap_font;
+pub mod contact_sheet;
+pub mod metadata;
+pub mod ordering;
+pub mod selection;
+pub mod thumbnail;
+pub mod validate;
@@ -0,0 +1,134 @@
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use crate::frames::metadata::{capture_time, modified_time};
+use crate::storage::local::get_frames_directory;
+
+/// How frames are ordered in the rendered video
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum FrameOrder {
+    /// Natural sort on filenames (IMG_2 before IMG_10)
+    #[default]
+    Filename,
+    /// EXIF `DateTimeOriginal`, for wrapped counters or mixed cameras
+    Exif,
+    /// File modification time
+    Mtime,
+    /// The order given in the request's `explicitOrder` list
+    Explicit,
+}
+
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    alphanumeric_sort::compare_str(a, b)
+}
+
+/// Sort frames by a timestamp; frames without one go last, in filename order
+fn sort_by_timestamp<F>(frames_dir: &Path, files: &mut Vec<String>, timestamp: F)
+where
+    F: Fn(&Path) -> Option<NaiveDateTime>,
+{
+    let mut keyed: Vec<(Option<NaiveDateTime>, String)> = files
+        .drain(..)
+        .map(|f| (timestamp(&frames_dir.join(&f)), f))
+        .collect();
+
+    keyed.sort_by(|(ta, fa), (tb, fb)| match (ta, tb) {
+        (Some(ta), Some(tb)) => ta.cmp(tb).then_with(|| natural_cmp(fa, fb)),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => natural_cmp(fa, fb),
+    });
+
+    files.extend(keyed.into_iter().map(|(_, f)| f));
+}
+
+/// Put a job's frames in render order.
+///
+/// `files` is the job's frame list; for [`FrameOrder::Explicit`] the result is
+/// exactly `explicit`, which must only name existing frames, each at most once.
+pub fn order_frames(
+    frames_dir: &Path,
+    mut files: Vec<String>,
+    order: FrameOrder,
+    explicit: Option<&[String]>,
+) -> Result<Vec<String>> {
+    match order {
+        FrameOrder::Filename => files.sort_by(|a, b| natural_cmp(a, b)),
+        FrameOrder::Exif => sort_by_timestamp(frames_dir, &mut files, capture_time),
+        FrameOrder::Mtime => sort_by_timestamp(frames_dir, &mut files, modified_time),
+        FrameOrder::Explicit => {
+            let Some(explicit) = explicit else {
+                anyhow::bail!("explicitOrder is required when order is \"explicit\"");
+            };
+
+            let existing: HashSet<&str> = files.iter().map(|f| f.as_str()).collect();
+            let mut seen = HashSet::new();
+            for name in explicit {
+                if !existing.contains(name.as_str()) {
+                    anyhow::bail!("explicitOrder names a frame that doesn't exist: {}", name);
+                }
+                if !seen.insert(name.as_str()) {
+                    anyhow::bail!("explicitOrder lists a frame more than once: {}", name);
+                }
+            }
+            if explicit.is_empty() {
+                anyhow::bail!("explicitOrder must list at least one frame");
+            }
+
+            return Ok(explicit.to_vec());
+        }
+    }
+
+    Ok(files)
+}
+
+/// Cache of computed frame orders, so paging through previews doesn't re-read
+/// the EXIF data of every frame on each request.
+///
+/// Entries are keyed by job and order, and thrown away when the frames directory
+/// changes (adding or removing a file updates its modification time).
+#[derive(Clone, Default)]
+pub struct FrameOrderCache {
+    entries: Arc<Mutex<HashMap<(String, FrameOrder), CachedOrder>>>,
+}
+
+/// A computed order and the frames directory's modification time it was computed at
+struct CachedOrder {
+    dir_modified: SystemTime,
+    files: Vec<String>,
+}
+
+impl FrameOrderCache {
+    pub fn get_or_compute<F>(
+        &self,
+        job_id: &str,
+        order: FrameOrder,
+        frames_dir: &Path,
+        compute: F,
+    ) -> Result<Vec<String>>
+    where
+        F: FnOnce() -> Result<Vec<String>>,
+    {
+        let dir_modified = std::fs::metadata(frames_dir)?.modified()?;
+        let key = (job_id.to_string(), order);
+
+        if let Some(cached) = self.entries.lock().unwrap().get(&key) {
+            if cached.dir_modified == dir_modified {
+                return Ok(cached.files.clone());
+            }
+        }
+
+        let files = compute()?;
+        let mut entries = self.entries.lock().unwrap();
+        // Forget jobs whose directories have been cleaned up
+        entries.retain(|(job_id, _), _| get_frames_directory(job_id).exists());
+        entries.insert(key, CachedOrder { dir_modified, files: files.clone() });
+        Ok(files)
+    }
+}
@@ -0,0 +1,302 @@
+use std::collections::HashSet;
+use std::path::Path;
+use chrono::{Datelike, NaiveDate, NaiveDateTime, NaiveTime, Weekday};
+use serde::{Deserialize, Serialize};
+use crate::frames::metadata::{capture_time, modified_time};
+
+/// Longest minimum interval between selected frames: 366 days
+const MAX_MIN_INTERVAL_SECONDS: u64 = 366 * 24 * 60 * 60;
+
+/// Where frame timestamps for the selection rules come from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TimestampSource {
+    /// EXIF `DateTimeOriginal`, falling back to the file modification time
+    #[default]
+    Exif,
+    /// File modification time
+    Mtime,
+}
+
+/// A daily time-of-day range; `start` after `end` wraps around midnight
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TimeWindow {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl TimeWindow {
+    fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            time >= self.start && time <= self.end
+        } else {
+            time >= self.start || time <= self.end
+        }
+    }
+}
+
+/// Rules that pick which frames of a job go into the video
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FrameSelection {
+    #[serde(default)]
+    pub timestamps: TimestampSource,
+    /// First day to include
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from: Option<NaiveDate>,
+    /// Last day to include
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub to: Option<NaiveDate>,
+    /// Days of the week to include, e.g. `["mon", "tue"]`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub weekdays: Option<Vec<Weekday>>,
+    /// Times of day to include; a frame must fall in at least one window
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub time_windows: Vec<TimeWindow>,
+    /// Minimum time between two selected frames
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_interval_seconds: Option<u64>,
+    /// Keep only every Nth of the remaining frames
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub every_nth: Option<usize>,
+}
+
+impl FrameSelection {
+    /// Whether any rule needs frame timestamps
+    fn uses_timestamps(&self) -> bool {
+        self.from.is_some()
+            || self.to.is_some()
+            || self.weekdays.is_some()
+            || !self.time_windows.is_empty()
+            || self.min_interval_seconds.is_some()
+    }
+
+    fn timestamp(&self, path: &Path) -> Option<NaiveDateTime> {
+        match self.timestamps {
+            TimestampSource::Exif => capture_time(path).or_else(|| modified_time(path)),
+            TimestampSource::Mtime => modified_time(path),
+        }
+    }
+
+    /// Whether a frame taken at `time` passes the date, weekday and time-of-day rules
+    fn matches(&self, time: NaiveDateTime) -> bool {
+        let date = time.date();
+        self.from.is_none_or(|from| date >= from)
+            && self.to.is_none_or(|to| date <= to)
+            && self.weekdays.as_ref().is_none_or(|days| days.contains(&date.weekday()))
+            && (self.time_windows.is_empty()
+                || self.time_windows.iter().any(|w| w.contains(time.time())))
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if let (Some(from), Some(to)) = (self.from, self.to) {
+            if from > to {
+                return Err("Selection 'from' date is after its 'to' date".to_string());
+            }
+        }
+        if self.weekdays.as_ref().is_some_and(|days| days.is_empty()) {
+            return Err("Selection weekdays must list at least one day".to_string());
+        }
+        if self.time_windows.iter().any(|w| w.start == w.end) {
+            return Err("Selection time windows must have different start and end times".to_string());
+        }
+        if self.min_interval_seconds.is_some_and(|interval| interval > MAX_MIN_INTERVAL_SECONDS) {
+            return Err(format!(
+                "Selection minIntervalSeconds must be at most {}",
+                MAX_MIN_INTERVAL_SECONDS
+            ));
+        }
+        if self.every_nth == Some(0) {
+            return Err("Selection everyNth must be at least 1".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Apply selection rules to frames in render order, keeping that order.
+///
+/// Date, weekday and time-of-day rules drop frames outside them, and frames
+/// without a timestamp. The minimum interval is then applied in capture order,
+/// and `everyNth` last, in render order.
+pub fn select_frames(frames_dir: &Path, files: Vec<String>, selection: &FrameSelection) -> Vec<String> {
+    let mut files = files;
+
+    if selection.uses_timestamps() {
+        let mut stamped: Vec<(NaiveDateTime, String)> = files
+            .into_iter()
+            .filter_map(|f| Some((selection.timestamp(&frames_dir.join(&f))?, f)))
+            .filter(|(time, _)| selection.matches(*time))
+            .collect();
+
+        if let Some(interval) = selection.min_interval_seconds {
+            let mut chronological: Vec<&(NaiveDateTime, String)> = stamped.iter().collect();
+            chronological.sort_by(|(ta, fa), (tb, fb)| {
+                ta.cmp(tb).then_with(|| alphanumeric_sort::compare_str(fa, fb))
+            });
+
+            // Validation bounds the interval, but never let a huge one panic
+            let interval = i64::try_from(interval)
+                .ok()
+                .and_then(chrono::Duration::try_seconds)
+                .unwrap_or(chrono::Duration::MAX);
+            let mut last_kept: Option<NaiveDateTime> = None;
+            let mut kept: HashSet<String> = HashSet::new();
+            for (time, filename) in chronological {
+                if last_kept.is_none_or(|last| *time - last >= interval) {
+                    last_kept = Some(*time);
+                    kept.insert(filename.clone());
+                }
+            }
+            stamped.retain(|(_, f)| kept.contains(f));
+        }
+
+        files = stamped.into_iter().map(|(_, f)| f).collect();
+    }
+
+    if let Some(n) = selection.every_nth.filter(|n| *n > 1) {
+        files = files.into_iter().step_by(n).collect();
+    }
+
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use std::time::{Duration, SystemTime};
+    use tempfile::TempDir;
+
+    /// Frames named after their modification time in minutes past a fixed
+    /// start, written in the order given
+    fn frames(minutes: &[u64]) -> (TempDir, Vec<String>) {
+        let dir = tempfile::tempdir().unwrap();
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let names = minutes
+            .iter()
+            .map(|minute| {
+                let name = format!("{}.png", minute);
+                let file = File::create(dir.path().join(&name)).unwrap();
+                file.set_modified(start + Duration::from_secs(minute * 60)).unwrap();
+                name
+            })
+            .collect();
+        (dir, names)
+    }
+
+    fn by_mtime() -> FrameSelection {
+        FrameSelection {
+            timestamps: TimestampSource::Mtime,
+            ..FrameSelection::default()
+        }
+    }
+
+    #[test]
+    fn no_rules_keep_every_frame() {
+        let (dir, files) = frames(&[0, 1, 2]);
+
+        assert_eq!(select_frames(dir.path(), files.clone(), &FrameSelection::default()), files);
+    }
+
+    #[test]
+    fn every_nth_keeps_the_first_of_each_run() {
+        let (dir, files) = frames(&[0, 1, 2, 3, 4, 5, 6]);
+        let select = |n| {
+            let selection = FrameSelection { every_nth: Some(n), ..FrameSelection::default() };
+            select_frames(dir.path(), files.clone(), &selection)
+        };
+
+        assert_eq!(select(1), files);
+        assert_eq!(select(3), ["0.png", "3.png", "6.png"]);
+        assert_eq!(select(100), ["0.png"]);
+    }
+
+    #[test]
+    fn min_interval_is_applied_in_capture_order_and_keeps_render_order() {
+        // Rendered newest first
+        let (dir, files) = frames(&[30, 25, 20, 12, 10, 5, 0]);
+        let selection = FrameSelection {
+            min_interval_seconds: Some(10 * 60),
+            ..by_mtime()
+        };
+
+        assert_eq!(
+            select_frames(dir.path(), files, &selection),
+            ["30.png", "20.png", "10.png", "0.png"]
+        );
+    }
+
+    #[test]
+    fn min_interval_counts_an_exact_gap() {
+        let (dir, files) = frames(&[0, 9, 10, 19, 20]);
+        let selection = FrameSelection {
+            min_interval_seconds: Some(10 * 60),
+            ..by_mtime()
+        };
+
+        assert_eq!(select_frames(dir.path(), files, &selection), ["0.png", "10.png", "20.png"]);
+    }
+
+    #[test]
+    fn min_interval_is_bounded() {
+        let selection = |seconds| FrameSelection {
+            min_interval_seconds: Some(seconds),
+            ..by_mtime()
+        };
+
+        assert!(selection(MAX_MIN_INTERVAL_SECONDS).validate().is_ok());
+        assert!(selection(MAX_MIN_INTERVAL_SECONDS + 1).validate().is_err());
+        assert!(selection(u64::MAX).validate().is_err());
+    }
+
+    #[test]
+    fn huge_min_interval_keeps_only_the_first_frame() {
+        let (dir, files) = frames(&[0, 10, 20]);
+        let selection = FrameSelection {
+            min_interval_seconds: Some(u64::MAX),
+            ..by_mtime()
+        };
+
+        assert_eq!(select_frames(dir.path(), files, &selection), ["0.png"]);
+    }
+
+    #[test]
+    fn every_nth_applies_after_the_interval() {
+        let (dir, files) = frames(&[0, 1, 10, 11, 20, 21, 30]);
+        let selection = FrameSelection {
+            min_interval_seconds: Some(10 * 60),
+            every_nth: Some(2),
+            ..by_mtime()
+        };
+
+        // The interval keeps 0, 10, 20 and 30; every other one of those remains
+        assert_eq!(select_frames(dir.path(), files, &selection), ["0.png", "20.png"]);
+    }
+
+    #[test]
+    fn date_range_is_inclusive() {
+        // Two days apart, so the frames fall on different dates in any time zone
+        let (dir, files) = frames(&[0, 2 * 24 * 60, 4 * 24 * 60]);
+        let date = |name: &str| modified_time(&dir.path().join(name)).unwrap().date();
+        let selection = FrameSelection {
+            from: Some(date(&files[1])),
+            to: Some(date(&files[2])),
+            ..by_mtime()
+        };
+
+        assert_eq!(select_frames(dir.path(), files.clone(), &selection), files[1..]);
+    }
+
+    #[test]
+    fn time_window_wraps_around_midnight() {
+        let window = TimeWindow {
+            start: NaiveTime::from_hms_opt(22, 0, 0).unwrap(),
+            end: NaiveTime::from_hms_opt(2, 0, 0).unwrap(),
+        };
+
+        assert!(window.contains(NaiveTime::from_hms_opt(23, 30, 0).unwrap()));
+        assert!(window.contains(NaiveTime::from_hms_opt(2, 0, 0).unwrap()));
+        assert!(!window.contains(NaiveTime::from_hms_opt(12, 0, 0).unwrap()));
+    }
+}
@@ -0,0 +1,149 @@
+use std::fs;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use anyhow::{Result, Context};
+use image::{DynamicImage, ImageReader};
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::png::PngEncoder;
+use image::codecs::webp::WebPEncoder;
+use serde::Deserialize;
+
+/// Largest thumbnail side that can be asked for
+pub const MAX_THUMBNAIL_SIDE: u32 = 2048;
+
+/// JPEG quality of thumbnails; they're for looking at, not for rendering
+const JPEG_QUALITY: u8 = 85;
+
+/// Image formats thumbnails can be encoded as
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ThumbnailFormat {
+    #[default]
+    Jpeg,
+    Png,
+    /// Lossless WebP
+    Webp,
+}
+
+impl ThumbnailFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ThumbnailFormat::Jpeg => "jpg",
+            ThumbnailFormat::Png => "png",
+            ThumbnailFormat::Webp => "webp",
+        }
+    }
+
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            ThumbnailFormat::Jpeg => "image/jpeg",
+            ThumbnailFormat::Png => "image/png",
+            ThumbnailFormat::Webp => "image/webp",
+        }
+    }
+}
+
+/// Bounding box and format of a thumbnail; an unset side doesn't constrain it
+#[derive(Debug, Clone, Copy)]
+pub struct ThumbnailSpec {
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub format: ThumbnailFormat,
+}
+
+impl ThumbnailSpec {
+    pub fn validate(&self) -> Result<(), String> {
+        for side in [self.width, self.height].into_iter().flatten() {
+            if !(1..=MAX_THUMBNAIL_SIDE).contains(&side) {
+                return Err(format!(
+                    "Thumbnail width and height must be between 1 and {}",
+                    MAX_THUMBNAIL_SIDE
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Cache file name for a thumbnail of `frame`, e.g. `img_1.png.320x0.jpg`
+    fn file_name(&self, frame: &str) -> String {
+        format!(
+            "{}.{}x{}.{}",
+            frame,
+            self.width.unwrap_or(0),
+            self.height.unwrap_or(0),
+            self.format.extension()
+        )
+    }
+
+    /// Shrink `image` to fit the box, keeping its aspect ratio; never enlarges
+    fn resize(&self, image: DynamicImage) -> DynamicImage {
+        let width = self.width.unwrap_or(u32::MAX).min(image.width());
+        let height = self.height.unwrap_or(u32::MAX).min(image.height());
+        if width == image.width() && height == image.height() {
+            image
+        } else {
+            image.thumbnail(width, height)
+        }
+    }
+}
+
+/// Encode `image` in `format` to `writer`
+fn encode<W: Write>(image: &DynamicImage, format: ThumbnailFormat, writer: W) -> Result<()> {
+    let mut writer = BufWriter::new(writer);
+    match format {
+        ThumbnailFormat::Jpeg => {
+            image.to_rgb8().write_with_encoder(JpegEncoder::new_with_quality(&mut writer, JPEG_QUALITY))?
+        }
+        ThumbnailFormat::Png => image.write_with_encoder(PngEncoder::new(&mut writer))?,
+        ThumbnailFormat::Webp => image.to_rgba8().write_with_encoder(WebPEncoder::new_lossless(&mut writer))?,
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Encode `image` in `format` and store it at `path`, creating its directory.
+///
+/// The image is written to a temporary file next to `path` first, so a
+/// concurrent request never serves a half-written file.
+pub fn save(image: &DynamicImage, format: ThumbnailFormat, path: &Path) -> Result<()> {
+    let dir = path.parent().context("Image path has no directory")?;
+    fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let mut tmp = tempfile::Builder::new()
+        .prefix(".partial")
+        .tempfile_in(dir)
+        .context("Failed to create temporary image file")?;
+    encode(image, format, tmp.as_file_mut())?;
+    tmp.persist(path)
+        .with_context(|| format!("Failed to store {}", path.display()))?;
+    Ok(())
+}
+
+/// Whether a cached thumbnail is at least as new as its frame
+fn is_fresh(thumbnail: &Path, frame: &Path) -> bool {
+    let modified = |path: &Path| fs::metadata(path).and_then(|m| m.modified()).ok();
+    match (modified(thumbnail), modified(frame)) {
+        (Some(thumbnail), Some(frame)) => thumbnail >= frame,
+        _ => false,
+    }
+}
+
+/// Path of a thumbnail of the frame `filename` in `frames_dir`, generating it
+/// into `cache_dir` unless an up-to-date copy is already there
+pub fn thumbnail(frames_dir: &Path, filename: &str, cache_dir: &Path, spec: &ThumbnailSpec) -> Result<PathBuf> {
+    let frame_path = frames_dir.join(filename);
+    let path = cache_dir.join(spec.file_name(filename));
+    if is_fresh(&path, &frame_path) {
+        return Ok(path);
+    }
+
+    let image = ImageReader::open(&frame_path)
+        .with_context(|| format!("Failed to open {}", filename))?
+        .with_guessed_format()?
+        .decode()
+        .with_context(|| format!("Failed to decode {}", filename))?;
+    let image = spec.resize(image);
+
+    save(&image, spec.format, &path)?;
+
+    Ok(path)
+}
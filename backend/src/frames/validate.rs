@@ -0,0 +1,225 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// Image formats accepted as frames, identified by their magic bytes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImageFormat {
+    Png,
+    Jpeg,
+    WebP,
+}
+
+impl ImageFormat {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ImageFormat::Png => "PNG",
+            ImageFormat::Jpeg => "JPEG",
+            ImageFormat::WebP => "WebP",
+        }
+    }
+
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            ImageFormat::Png => "image/png",
+            ImageFormat::Jpeg => "image/jpeg",
+            ImageFormat::WebP => "image/webp",
+        }
+    }
+
+    fn from_extension(filename: &str) -> Option<Self> {
+        let ext = filename.rsplit_once('.')?.1.to_lowercase();
+        match ext.as_str() {
+            "png" => Some(ImageFormat::Png),
+            "jpg" | "jpeg" => Some(ImageFormat::Jpeg),
+            "webp" => Some(ImageFormat::WebP),
+            _ => None,
+        }
+    }
+}
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// Identify an image format from the first bytes of a file
+pub fn sniff_format(header: &[u8]) -> Option<ImageFormat> {
+    if header.starts_with(PNG_SIGNATURE) {
+        Some(ImageFormat::Png)
+    } else if header.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        Some(ImageFormat::Jpeg)
+    } else if header.len() >= 12 && &header[0..4] == b"RIFF" && &header[8..12] == b"WEBP" {
+        Some(ImageFormat::WebP)
+    } else {
+        None
+    }
+}
+
+/// Sniff the format of a file on disk without validating the rest of it
+pub fn detect_format(path: &Path) -> Option<ImageFormat> {
+    let mut file = File::open(path).ok()?;
+    let mut header = [0u8; 16];
+    let read = read_up_to(&mut file, &mut header).ok()?;
+    sniff_format(&header[..read])
+}
+
+/// Check that a frame's content matches its extension and isn't truncated.
+///
+/// Returns the detected format, or a human-readable reason the frame can't be used.
+pub fn validate_frame(path: &Path) -> Result<ImageFormat, String> {
+    let filename = path.file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let expected = ImageFormat::from_extension(&filename)
+        .ok_or_else(|| "Unsupported file extension".to_string())?;
+
+    let mut file = File::open(path).map_err(|e| format!("Failed to read file: {}", e))?;
+    let mut header = [0u8; 16];
+    let read = read_up_to(&mut file, &mut header)
+        .map_err(|e| format!("Failed to read file: {}", e))?;
+
+    let format = sniff_format(&header[..read])
+        .ok_or_else(|| "Content is not a PNG, JPEG or WebP image".to_string())?;
+    if format != expected {
+        return Err(format!(
+            "Content is {} but the file extension says {}",
+            format.name(),
+            expected.name()
+        ));
+    }
+
+    let structure = match format {
+        ImageFormat::Png => check_png(&mut file),
+        ImageFormat::Jpeg => check_jpeg(&mut file),
+        ImageFormat::WebP => check_webp(&mut file, &header),
+    };
+    structure.map(|_| format)
+}
+
+fn read_up_to(file: &mut File, buf: &mut [u8]) -> std::io::Result<usize> {
+    let mut total = 0;
+    while total < buf.len() {
+        let n = file.read(&mut buf[total..])?;
+        if n == 0 {
+            break;
+        }
+        total += n;
+    }
+    Ok(total)
+}
+
+fn file_len(file: &mut File) -> Result<u64, String> {
+    file.seek(SeekFrom::End(0)).map_err(|e| format!("Failed to read file: {}", e))
+}
+
+/// Walk the chunk list without reading chunk data; the image must reach IEND
+fn check_png(file: &mut File) -> Result<(), String> {
+    let len = file_len(file)?;
+    let mut pos = PNG_SIGNATURE.len() as u64;
+
+    while pos + 8 <= len {
+        file.seek(SeekFrom::Start(pos)).map_err(|e| e.to_string())?;
+        let mut chunk_header = [0u8; 8];
+        file.read_exact(&mut chunk_header).map_err(|e| e.to_string())?;
+
+        let data_len = u32::from_be_bytes([chunk_header[0], chunk_header[1], chunk_header[2], chunk_header[3]]) as u64;
+        let chunk_end = pos + 8 + data_len + 4;
+        if chunk_end > len {
+            return Err("PNG is truncated".to_string());
+        }
+        if &chunk_header[4..8] == b"IEND" {
+            return Ok(());
+        }
+        pos = chunk_end;
+    }
+
+    Err("PNG is truncated (no IEND chunk)".to_string())
+}
+
+/// Walk the marker segments up to the scan, then look for the end-of-image marker.
+///
+/// Entropy-coded data can't contain FF D9, so a missing marker after the start of
+/// scan means the file was cut off. Segments (including the EXIF thumbnail, which
+/// has its own end marker) are skipped by length, and everything from the scan
+/// on is searched, as cameras can append large data after the marker, like the
+/// extra images of MPF or Ultra HDR files.
+fn check_jpeg(file: &mut File) -> Result<(), String> {
+    let len = file_len(file)?;
+    let mut pos = 2u64;
+
+    let scan_start = loop {
+        if pos + 4 > len {
+            return Err("JPEG is truncated (no image data)".to_string());
+        }
+        file.seek(SeekFrom::Start(pos)).map_err(|e| e.to_string())?;
+        let mut marker = [0u8; 4];
+        file.read_exact(&mut marker).map_err(|e| e.to_string())?;
+
+        if marker[0] != 0xFF {
+            return Err("JPEG is corrupt (invalid marker)".to_string());
+        }
+        match marker[1] {
+            // Fill bytes before a marker
+            0xFF => pos += 1,
+            // Start of scan: entropy-coded data follows its header
+            0xDA => break pos,
+            // Standalone markers have no length
+            0x01 | 0xD0..=0xD7 => pos += 2,
+            _ => pos += 2 + u16::from_be_bytes([marker[2], marker[3]]) as u64,
+        }
+    };
+
+    file.seek(SeekFrom::Start(scan_start)).map_err(|e| e.to_string())?;
+    let mut reader = BufReader::new(file);
+    let mut previous = 0u8;
+    loop {
+        let buf = reader.fill_buf().map_err(|e| e.to_string())?;
+        if buf.is_empty() {
+            return Err("JPEG is truncated (no end-of-image marker)".to_string());
+        }
+        if previous == 0xFF && buf[0] == 0xD9 || buf.windows(2).any(|w| w == [0xFF, 0xD9]) {
+            return Ok(());
+        }
+        previous = buf[buf.len() - 1];
+        let consumed = buf.len();
+        reader.consume(consumed);
+    }
+}
+
+/// The RIFF header records the full file size
+fn check_webp(file: &mut File, header: &[u8; 16]) -> Result<(), String> {
+    let len = file_len(file)?;
+    let riff_size = u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as u64;
+    if len < riff_size + 8 {
+        return Err("WebP is truncated".to_string());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    /// A JPEG's markers up to a tiny scan, `trailer` after the scan data
+    fn jpeg(trailer: &[u8]) -> File {
+        let mut file = tempfile::tempfile().unwrap();
+        file.write_all(&[0xFF, 0xD8, 0xFF, 0xDA, 0x00, 0x02, 0x12, 0x34]).unwrap();
+        file.write_all(trailer).unwrap();
+        file
+    }
+
+    #[test]
+    fn jpeg_may_carry_large_data_after_its_end_marker() {
+        // Like the gain map of an Ultra HDR photo
+        let mut trailer = vec![0xFF, 0xD9];
+        trailer.extend(std::iter::repeat_n(0x55, 1024 * 1024));
+
+        assert_eq!(check_jpeg(&mut jpeg(&trailer)), Ok(()));
+    }
+
+    #[test]
+    fn jpeg_without_end_marker_is_truncated() {
+        let err = check_jpeg(&mut jpeg(&[0x56; 100_000])).unwrap_err();
+
+        assert!(err.contains("no end-of-image marker"), "{}", err);
+    }
+}
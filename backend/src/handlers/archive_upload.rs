@@ -0,0 +1,149 @@
+use actix_multipart::Multipart;
+use actix_web::{web, HttpResponse, Error};
+use futures_util::TryStreamExt;
+use serde::Deserialize;
+use std::io::Write;
+use tempfile::NamedTempFile;
+use uuid::Uuid;
+use crate::config::Config;
+use crate::models::UploadResponse;
+use crate::storage::archive::{extract_archive, ArchiveKind, ExtractLimits, ExtractedFrames};
+use crate::storage::job_store::{JobStore, UploadRefused};
+use crate::storage::local::{cleanup_job, ensure_job_directory, get_frames_directory, get_job_directory, is_valid_job_id};
+
+/// Extracted data may be at most this many times larger than the archive itself
+const MAX_COMPRESSION_RATIO: u64 = 100;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArchiveUploadQuery {
+    /// Existing job to extract into; a new job is created when omitted
+    pub job_id: Option<String>,
+}
+
+/// Upload a `.zip`, `.tar` or `.tar.gz` of frames and extract its images into a job
+pub async fn upload_archive(
+    query: web::Query<ArchiveUploadQuery>,
+    mut payload: Multipart,
+    job_store: web::Data<JobStore>,
+    config: web::Data<Config>,
+) -> Result<HttpResponse, Error> {
+    // An existing job is held while its frames are written, so no render is queued meanwhile
+    let (job_id, is_new_job, _uploading) = match &query.job_id {
+        Some(job_id) => {
+            if !is_valid_job_id(job_id) {
+                return Err(actix_web::error::ErrorBadRequest("Invalid job ID"));
+            }
+            if !get_frames_directory(job_id).exists() {
+                return Err(actix_web::error::ErrorNotFound("Job not found"));
+            }
+            let uploading = match job_store.begin_upload(job_id) {
+                Ok(guard) => guard,
+                Err(UploadRefused::UnknownJob) => return Err(actix_web::error::ErrorNotFound("Job not found")),
+                Err(UploadRefused::Busy) => {
+                    return Ok(HttpResponse::Conflict().json(serde_json::json!({
+                        "error": "Job is queued or rendering"
+                    })));
+                }
+            };
+            (job_id.clone(), false, Some(uploading))
+        }
+        None => {
+            let job_id = Uuid::new_v4().to_string();
+            ensure_job_directory(&job_id)
+                .map_err(actix_web::error::ErrorInternalServerError)?;
+            (job_id, true, None)
+        }
+    };
+
+    // Adding frames restarts an existing job's retention
+    if !is_new_job {
+        job_store.touch(&job_id);
+    }
+    let result = receive_and_extract(&job_id, &mut payload, &config).await;
+    if !is_new_job {
+        job_store.touch(&job_id);
+    }
+
+    let extracted = match result {
+        Ok(Some(extracted)) if !extracted.filenames.is_empty() || !is_new_job => extracted,
+        Ok(_) => {
+            if is_new_job {
+                let _ = cleanup_job(&job_id);
+            }
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "No valid image files found in archive"
+            })));
+        }
+        Err(e) => {
+            if is_new_job {
+                let _ = cleanup_job(&job_id);
+            }
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": format!("{:#}", e)
+            })));
+        }
+    };
+
+    let file_count = extracted.filenames.len();
+    if is_new_job {
+        job_store.create_job(&job_id, file_count);
+    }
+
+    Ok(HttpResponse::Ok().json(UploadResponse {
+        job_id,
+        file_count,
+        filenames: extracted.filenames,
+        collisions: extracted.collisions,
+        rejected: extracted.rejected,
+    }))
+}
+
+/// Spool the first archive field to disk next to the job, then extract it.
+///
+/// Returns `None` if the request contained no archive.
+async fn receive_and_extract(
+    job_id: &str,
+    payload: &mut Multipart,
+    config: &Config,
+) -> anyhow::Result<Option<ExtractedFrames>> {
+    while let Ok(Some(mut field)) = payload.try_next().await {
+        let Some(kind) = field.content_disposition()
+            .get_filename()
+            .and_then(ArchiveKind::from_filename)
+        else {
+            continue;
+        };
+
+        // ZIP needs random access to its central directory, so the archive is
+        // written to a temp file first and entries are then extracted one by one
+        let job_dir = get_job_directory(job_id);
+        let mut spool = web::block(move || NamedTempFile::new_in(job_dir)).await??;
+
+        while let Some(chunk) = field.try_next()
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to receive archive: {}", e))?
+        {
+            spool = web::block(move || {
+                spool.write_all(&chunk)?;
+                Ok::<_, std::io::Error>(spool)
+            })
+            .await??;
+        }
+
+        let frames_dir = get_frames_directory(job_id);
+        let limits = ExtractLimits {
+            max_entries: config.archive_max_entries,
+            max_total_bytes: config.archive_max_extracted_gb * 1024 * 1024 * 1024,
+            max_ratio: MAX_COMPRESSION_RATIO,
+        };
+        let extracted = web::block(move || {
+            extract_archive(spool.path(), kind, &frames_dir, &limits)
+        })
+        .await??;
+
+        return Ok(Some(extracted));
+    }
+
+    Ok(None)
+}
@@ -0,0 +1,40 @@
+use actix_web::{web, HttpResponse, Error};
+use crate::models::{CancelRenderResponse, JobStatusType};
+use crate::storage::job_store::JobStore;
+use crate::storage::local::is_valid_job_id;
+use crate::video::queue::{Cancellation, RenderQueue};
+
+pub async fn cancel_render(
+    path: web::Path<String>,
+    job_store: web::Data<JobStore>,
+    render_queue: web::Data<RenderQueue>,
+) -> Result<HttpResponse, Error> {
+    let job_id = path.into_inner();
+    if !is_valid_job_id(&job_id) {
+        return Err(actix_web::error::ErrorBadRequest("Invalid job ID"));
+    }
+
+    if job_store.get(&job_id).is_none() {
+        return Err(actix_web::error::ErrorNotFound("Job not found"));
+    }
+
+    match render_queue.cancel(&job_id) {
+        Cancellation::NotRendering => Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Job is not queued or rendering"
+        }))),
+        Cancellation::Dequeued => {
+            job_store.set_status(&job_id, JobStatusType::Cancelled);
+            Ok(HttpResponse::Ok().json(CancelRenderResponse {
+                job_id,
+                status: JobStatusType::Cancelled.as_str().to_string(),
+            }))
+        }
+        // The worker records the cancellation once FFmpeg is gone and the
+        // partial output removed; until then the job stays processing, so it
+        // can't be rendered again while the old render is still cleaning up
+        Cancellation::Signalled => Ok(HttpResponse::Accepted().json(CancelRenderResponse {
+            job_id,
+            status: "cancelling".to_string(),
+        })),
+    }
+}
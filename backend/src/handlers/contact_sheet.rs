@@ -0,0 +1,73 @@
+use actix_web::{web, HttpRequest, HttpResponse, Error};
+use serde::Deserialize;
+use crate::frames::contact_sheet::{contact_sheet, ContactSheetSpec};
+use crate::frames::ordering::{FrameOrder, FrameOrderCache};
+use crate::frames::thumbnail::ThumbnailFormat;
+use crate::handlers::preview::{preview_frames, serve_image};
+use crate::storage::job_store::JobStore;
+use crate::storage::local::{get_contact_sheets_directory, get_frames_directory, get_thumbnails_directory, is_valid_job_id};
+
+fn default_grid_side() -> u32 {
+    6
+}
+
+fn default_tile_width() -> u32 {
+    240
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContactSheetQuery {
+    /// Overrides the order saved with the job's last render request
+    order: Option<FrameOrder>,
+    #[serde(default = "default_grid_side")]
+    columns: u32,
+    #[serde(default = "default_grid_side")]
+    rows: u32,
+    #[serde(default = "default_tile_width")]
+    tile_width: u32,
+    #[serde(default)]
+    format: ThumbnailFormat,
+}
+
+/// Serve a grid of evenly spaced frames of the job, labelled with their
+/// preview indexes and capture times
+pub async fn get_contact_sheet(
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<ContactSheetQuery>,
+    job_store: web::Data<JobStore>,
+    frame_order_cache: web::Data<FrameOrderCache>,
+) -> Result<HttpResponse, Error> {
+    let job_id = path.into_inner();
+    if !is_valid_job_id(&job_id) {
+        return Err(actix_web::error::ErrorBadRequest("Invalid job ID"));
+    }
+    let spec = ContactSheetSpec {
+        columns: query.columns,
+        rows: query.rows,
+        tile_width: query.tile_width,
+        format: query.format,
+    };
+    spec.validate().map_err(actix_web::error::ErrorBadRequest)?;
+
+    let frames_dir = get_frames_directory(&job_id);
+    if !frames_dir.exists() {
+        return Err(actix_web::error::ErrorNotFound("Job not found"));
+    }
+
+    let files = preview_frames(&job_id, query.order, &job_store, frame_order_cache).await?;
+    if files.is_empty() {
+        return Err(actix_web::error::ErrorNotFound("Job has no frames"));
+    }
+
+    // Decoding a few dozen frames is slow, so keep it off the async workers
+    let thumbnails_dir = get_thumbnails_directory(&job_id);
+    let cache_dir = get_contact_sheets_directory(&job_id);
+    let sheet_path = web::block(move || contact_sheet(&frames_dir, &files, &thumbnails_dir, &cache_dir, &spec))
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    serve_image(&req, &sheet_path, spec.format.mime_type())
+}
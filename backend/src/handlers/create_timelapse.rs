@@ -1,85 +1,170 @@
 use actix_web::{web, HttpResponse, Error};
-use std::sync::{Arc, Mutex};
-use std::collections::HashMap;
-use crate::models::{CreateTimelapseRequest, CreateTimelapseResponse, JobStatusType};
-use crate::storage::local::{get_frames_directory, get_output_path};
-use crate::video::processor::create_timelapse_async;
+use crate::models::{CreateTimelapseRequest, CreateTimelapseResponse};
+use crate::storage::job_store::JobStore;
+use crate::storage::local::{get_frames_directory, is_valid_job_id};
+use crate::video::codec::Encoding;
+use crate::video::filters::{validate_crop_bounds, validate_geometry};
+use crate::video::processor::frames_for_render;
+use crate::video::pacing::{fit_to_duration, frame_durations, stepped_frame_count, validate_pacing, MIN_FPS};
+use crate::video::queue::{QueueFull, RenderQueue};
+use crate::video::rendition::{max_output_fps, validate_interpolation_fps, validate_renditions};
 
-pub type JobStore = Arc<Mutex<HashMap<String, JobStatusType>>>;
+/// Check every setting of a render request, as the render itself would see them
+pub fn validate_settings(req: &CreateTimelapseRequest) -> Result<(), String> {
+    // Validate rotation
+    if !matches!(req.rotation, 0 | 90 | 180 | 270) {
+        return Err("Invalid rotation. Must be 0, 90, 180, or 270".to_string());
+    }
+
+    // Validate fps, unless it is worked out from a target duration
+    let max_fps = max_output_fps(req);
+    if req.target_duration_seconds.is_none() && !(MIN_FPS..=max_fps).contains(&req.fps) {
+        return Err(format!("FPS must be between {} and {}", MIN_FPS, max_fps));
+    }
+    if req.frame_step == 0 {
+        return Err("frameStep must be at least 1".to_string());
+    }
+    // A target duration picks the frame step itself
+    if req.target_duration_seconds.is_some() && req.frame_step != 1 {
+        return Err("frameStep can't be combined with targetDurationSeconds".to_string());
+    }
+
+    // Validate codec, container, quality and preset together, then geometry, pacing, selection, deflicker, stabilization, interpolation, renditions and draft
+    Encoding::from_request(req)
+        .and_then(|_| validate_geometry(req))
+        .and_then(|_| validate_pacing(req))
+        .and_then(|_| req.selection.as_ref().map_or(Ok(()), |s| s.validate()))
+        .and_then(|_| req.deflicker.as_ref().map_or(Ok(()), |d| d.validate()))
+        .and_then(|_| req.stabilize.as_ref().map_or(Ok(()), |s| s.validate()))
+        .and_then(|_| req.interpolation.as_ref().map_or(Ok(()), |i| i.validate()))
+        .and_then(|_| validate_renditions(req))
+        .and_then(|_| req.draft.as_ref().map_or(Ok(()), |d| d.validate()))
+        // A target duration's frame rate is only known once it has been fitted
+        .and_then(|_| match req.target_duration_seconds {
+            Some(_) => Ok(()),
+            None => validate_interpolation_fps(req),
+        })
+}
 
 pub async fn create_timelapse_handler(
     req: web::Json<CreateTimelapseRequest>,
     job_store: web::Data<JobStore>,
+    render_queue: web::Data<RenderQueue>,
 ) -> Result<HttpResponse, Error> {
     let job_id = req.job_id.clone();
-    let rotation = req.rotation;
-    let fps = req.fps;
-    
-    // Validate rotation
-    if !matches!(rotation, 0 | 90 | 180 | 270) {
+    if !is_valid_job_id(&job_id) {
         return Ok(HttpResponse::BadRequest().json(serde_json::json!({
-            "error": "Invalid rotation. Must be 0, 90, 180, or 270"
+            "error": "Invalid job ID"
         })));
     }
-    
-    // Validate fps
-    if fps == 0 || fps > 60 {
+
+    if let Err(e) = validate_settings(&req) {
         return Ok(HttpResponse::BadRequest().json(serde_json::json!({
-            "error": "FPS must be between 1 and 60"
+            "error": e
         })));
     }
     
     // Check if job exists
     let frames_dir = get_frames_directory(&job_id);
-    if !frames_dir.exists() {
+    if job_store.get(&job_id).is_none() || !frames_dir.exists() {
         return Ok(HttpResponse::NotFound().json(serde_json::json!({
             "error": "Job not found"
         })));
     }
-    
-    // Update job status to processing (with no progress yet)
-    {
-        let mut store = job_store.lock().unwrap();
-        store.insert(job_id.clone(), JobStatusType::Processing(None));
-    }
-    
-    // Spawn async task to process video
-    let job_id_clone = job_id.clone();
-    // Clone the inner Arc (web::Data wraps in another Arc, so get_ref gives us &Arc<...>)
-    let job_store_arc = Arc::clone(job_store.get_ref());
 
-    actix_web::rt::spawn(async move {
-        let frames_dir = get_frames_directory(&job_id_clone);
-        let output_path = get_output_path(&job_id_clone);
+    // Order and select the frames now, so a bad explicit order, a selection
+    // that matches nothing or a crop outside the frames is reported to the
+    // client instead of failing the render
+    let frames = {
+        let job_id = job_id.clone();
+        let frames_dir = frames_dir.clone();
+        let settings = req.clone();
+        web::block(move || {
+            let frames = frames_for_render(&job_id, &frames_dir, &settings)?;
+            if let Some(first) = frames.first() {
+                validate_crop_bounds(&settings, &frames_dir.join(first)).map_err(anyhow::Error::msg)?;
+            }
+            Ok::<_, anyhow::Error>(frames)
+        })
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+    };
+    let frame_count = match frames {
+        Ok(frames) if frames.is_empty() => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "No frames match the selection"
+            })));
+        }
+        Ok(frames) => frames.len(),
+        Err(e) => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": e.to_string()
+            })));
+        }
+    };
 
-        match create_timelapse_async(
-            &job_id_clone,
-            frames_dir,
-            output_path,
-            fps,
-            rotation,
-            job_store_arc.clone(),
-        ).await {
-            Ok(_) => {
-                let mut store = job_store_arc.lock().unwrap();
-                store.insert(job_id_clone.clone(), JobStatusType::Completed);
+    // Turn a target duration into the frame rate and frame step that produce it
+    let mut settings = req.into_inner();
+    if let Some(target) = settings.target_duration_seconds {
+        match fit_to_duration(frame_count, &settings, target, max_output_fps(&settings)) {
+            Ok(fit) => {
+                settings.fps = fit.fps;
+                settings.frame_step = fit.frame_step;
             }
             Err(e) => {
-                let error_msg = format!("{}", e);
-                let mut store = job_store_arc.lock().unwrap();
-                // Truncate error message if too long for UI
-                let display_error = if error_msg.len() > 500 {
-                    format!("{}...", &error_msg[..500])
-                } else {
-                    error_msg
-                };
-                store.insert(job_id_clone.clone(), JobStatusType::Failed(display_error));
+                return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                    "error": e
+                })));
             }
         }
-    });
+        if let Err(e) = validate_interpolation_fps(&settings) {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": e
+            })));
+        }
+    }
+    let rendered_frames = stepped_frame_count(frame_count, settings.frame_step);
+    let duration_seconds: f64 = frame_durations(rendered_frames, &settings).iter().sum();
+    let duration_seconds = (duration_seconds * 1000.0).round() / 1000.0;
+    let fps = settings.fps;
+    let frame_step = settings.frame_step;
+    let frame_count = match &settings.draft {
+        Some(draft) => draft.frame_count(rendered_frames),
+        None => rendered_frames,
+    };
+
+    // Mark the job as waiting before a worker can pick it up. Re-rendering a
+    // job that is still waiting or running would race on its output, and frames
+    // still arriving would change underneath the render
+    let Some((previous_status, previous_settings)) = job_store.try_mark_queued(&job_id, &settings) else {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Job is already queued, rendering or receiving frames"
+        })));
+    };
+
+    let queue_position = match render_queue.enqueue(&job_id, settings) {
+        Ok(position) => position,
+        Err(QueueFull) => {
+            // The render never happened, so the job keeps describing the last one
+            job_store.restore(&job_id, previous_status, previous_settings);
+            return Ok(HttpResponse::ServiceUnavailable()
+                .append_header(("Retry-After", "30"))
+                .json(serde_json::json!({
+                    "error": "Render queue is full, please try again later"
+                })));
+        }
+    };
+
+    // Event streams saw the queued status before the job had a queue position
+    job_store.announce(&job_id);
     
     Ok(HttpResponse::Ok().json(CreateTimelapseResponse {
         job_id,
-        status: "processing".to_string(),
+        status: "queued".to_string(),
+        queue_position: Some(queue_position),
+        fps,
+        frame_step,
+        frame_count,
+        duration_seconds,
     }))
 }
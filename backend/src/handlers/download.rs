@@ -1,22 +1,158 @@
-use actix_web::{web, HttpResponse, Error};
-use crate::storage::local::get_output_path;
+use std::path::PathBuf;
+use actix_files::NamedFile;
+use actix_web::http::header::{self, ContentDisposition, DispositionParam, DispositionType, HeaderValue};
+use actix_web::{web, HttpRequest, HttpResponse, Error};
+use serde::Deserialize;
+use crate::storage::job_store::JobStore;
+use crate::storage::local::{get_draft_path, get_output_path, get_rendition_path, is_valid_job_id};
+use crate::video::codec::Container;
+use crate::video::rendition::is_valid_rendition_id;
 
+/// The job's rendered video and its container, if a render has finished
+fn find_output(job_id: &str) -> Option<(PathBuf, Container)> {
+    newest(Container::ALL.map(|container| (get_output_path(job_id, container.extension()), container)))
+}
+
+/// One rendition of the job's render and its container, if it has been written
+fn find_rendition(job_id: &str, rendition_id: &str) -> Option<(PathBuf, Container)> {
+    newest(Container::ALL.map(|container| {
+        (get_rendition_path(job_id, rendition_id, container.extension()), container)
+    }))
+}
+
+/// The most recently written of the candidate outputs. Renders only ever move
+/// finished files to these paths, and one that switched containers has moved
+/// its output in before it removes the previous render's
+fn newest(candidates: impl IntoIterator<Item = (PathBuf, Container)>) -> Option<(PathBuf, Container)> {
+    candidates
+        .into_iter()
+        .filter_map(|(path, container)| {
+            let modified = path.metadata().and_then(|m| m.modified()).ok()?;
+            Some((modified, path, container))
+        })
+        .max_by_key(|(modified, _, _)| *modified)
+        .map(|(_, path, container)| (path, container))
+}
+
+/// How the browser should treat a download
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Disposition {
+    /// Play it in the page
+    #[default]
+    Inline,
+    /// Save it as a file
+    Attachment,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DownloadQuery {
+    #[serde(default)]
+    disposition: Disposition,
+}
+
+/// Stream an output from disk. Range requests get `206 Partial Content` so
+/// players can seek before the whole file has arrived, and the `ETag` and
+/// `Last-Modified` validators let clients revalidate instead of downloading
+/// the file again.
+async fn serve(
+    req: &HttpRequest,
+    output_path: PathBuf,
+    container: Container,
+    filename: &str,
+    disposition: Disposition,
+) -> Result<HttpResponse, Error> {
+    let mime_type = container
+        .mime_type()
+        .parse()
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let disposition = ContentDisposition {
+        disposition: match disposition {
+            Disposition::Inline => DispositionType::Inline,
+            Disposition::Attachment => DispositionType::Attachment,
+        },
+        parameters: vec![DispositionParam::Filename(format!("{}.{}", filename, container.extension()))],
+    };
+
+    let file = NamedFile::open_async(&output_path)
+        .await?
+        .set_content_type(mime_type)
+        .set_content_disposition(disposition);
+
+    let mut response = file.into_response(req);
+    // Re-renders replace the file in place, so always check the validators
+    response
+        .headers_mut()
+        .insert(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+    Ok(response)
+}
+
+/// Serve the job's video. For a render with several renditions this is the
+/// first one.
 pub async fn download_video(
+    req: HttpRequest,
     path: web::Path<String>,
+    query: web::Query<DownloadQuery>,
+    job_store: web::Data<JobStore>,
 ) -> Result<HttpResponse, Error> {
     let job_id = path.into_inner();
-    let output_path = get_output_path(&job_id);
-    
-    if !output_path.exists() {
-        return Err(actix_web::error::ErrorNotFound("Video not found"));
+    if !is_valid_job_id(&job_id) {
+        return Err(actix_web::error::ErrorBadRequest("Invalid job ID"));
     }
-    
-    let file_data = std::fs::read(&output_path)
-        .map_err(actix_web::error::ErrorInternalServerError)?;
-    
-    Ok(HttpResponse::Ok()
-        .content_type("video/mp4")
-        .append_header(("Content-Disposition", format!("inline; filename=\"timelapse_{}.mp4\"", job_id)))
-        .append_header(("Cache-Control", "no-cache, no-store, must-revalidate"))
-        .body(file_data))
+    let filename = format!("timelapse_{}", job_id);
+
+    if let Some((output_path, container)) = find_output(&job_id) {
+        return serve(&req, output_path, container, &filename, query.disposition).await;
+    }
+
+    let first_rendition = job_store
+        .settings(&job_id)
+        .and_then(|settings| settings.renditions?.into_iter().next());
+    match first_rendition.and_then(|r| find_rendition(&job_id, &r.id)) {
+        Some((output_path, container)) => {
+            serve(&req, output_path, container, &filename, query.disposition).await
+        }
+        None => Err(actix_web::error::ErrorNotFound("Video not found")),
+    }
+}
+
+/// Serve the job's draft render
+pub async fn download_draft(
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<DownloadQuery>,
+) -> Result<HttpResponse, Error> {
+    let job_id = path.into_inner();
+    if !is_valid_job_id(&job_id) {
+        return Err(actix_web::error::ErrorBadRequest("Invalid job ID"));
+    }
+    let draft_path = get_draft_path(&job_id);
+    if !draft_path.exists() {
+        return Err(actix_web::error::ErrorNotFound("Draft not found"));
+    }
+
+    let filename = format!("timelapse_{}_draft", job_id);
+    serve(&req, draft_path, Container::Mp4, &filename, query.disposition).await
+}
+
+/// Serve one rendition of the job's render
+pub async fn download_rendition(
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+    query: web::Query<DownloadQuery>,
+) -> Result<HttpResponse, Error> {
+    let (job_id, rendition_id) = path.into_inner();
+    if !is_valid_job_id(&job_id) {
+        return Err(actix_web::error::ErrorBadRequest("Invalid job ID"));
+    }
+    if !is_valid_rendition_id(&rendition_id) {
+        return Err(actix_web::error::ErrorNotFound("Rendition not found"));
+    }
+
+    let Some((output_path, container)) = find_rendition(&job_id, &rendition_id) else {
+        return Err(actix_web::error::ErrorNotFound("Rendition not found"));
+    };
+
+    let filename = format!("timelapse_{}_{}", job_id, rendition_id);
+    serve(&req, output_path, container, &filename, query.disposition).await
 }
@@ -0,0 +1,129 @@
+use std::time::Duration;
+use actix_web::{web, HttpResponse, Error};
+use actix_web::web::Bytes;
+use futures_util::{stream, StreamExt};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::time::{interval, Interval, MissedTickBehavior};
+use crate::models::{JobStatus, JobStatusType};
+use crate::storage::job_store::{JobEvent, JobStore};
+use crate::storage::local::is_valid_job_id;
+use crate::video::queue::RenderQueue;
+
+/// How often a comment is sent on an idle stream so proxies don't close it
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// State of one client's event stream
+struct EventStream {
+    job_id: String,
+    events: broadcast::Receiver<JobEvent>,
+    job_store: web::Data<JobStore>,
+    render_queue: web::Data<RenderQueue>,
+    keepalive: Interval,
+    /// Last payload sent, so unchanged statuses aren't repeated
+    last_sent: Option<String>,
+    last_was_queued: bool,
+    /// Set once a final status has been sent
+    finished: bool,
+}
+
+impl EventStream {
+    /// Current status of the job from the store, `None` once it has been removed
+    fn snapshot(&self) -> Option<JobStatusType> {
+        self.job_store.status(&self.job_id)
+    }
+
+    /// Turn a status into an SSE message, or `None` if it's what the client already has
+    fn message(&mut self, status: &JobStatusType) -> Option<Bytes> {
+        let queue_position = match status {
+            JobStatusType::Queued => self.render_queue.position(&self.job_id),
+            _ => None,
+        };
+        let settings = self.job_store.settings(&self.job_id);
+        let body = JobStatus::new(status, queue_position, settings.as_ref());
+        let data = serde_json::to_string(&body).ok()?;
+        if self.last_sent.as_deref() == Some(data.as_str()) {
+            return None;
+        }
+
+        self.finished = body.is_final();
+        self.last_was_queued = matches!(status, JobStatusType::Queued);
+        let message = format!("event: {}\ndata: {}\n\n", body.status, data);
+        self.last_sent = Some(data);
+        Some(Bytes::from(message))
+    }
+
+    /// Wait for the next message to send, or `None` to end the stream
+    async fn next(&mut self) -> Option<Bytes> {
+        if self.finished {
+            return None;
+        }
+
+        loop {
+            let status = tokio::select! {
+                event = self.events.recv() => match event {
+                    Ok(event) if event.job_id == self.job_id => event.status,
+                    // Another job starting or leaving the queue moves this one up
+                    Ok(_) if self.last_was_queued => self.snapshot()?,
+                    Ok(_) => continue,
+                    // Missed some updates; the latest state is all the client needs
+                    Err(RecvError::Lagged(_)) => self.snapshot()?,
+                    Err(RecvError::Closed) => return None,
+                },
+                _ = self.keepalive.tick() => return Some(Bytes::from_static(b": keepalive\n\n")),
+            };
+
+            if let Some(message) = self.message(&status) {
+                return Some(message);
+            }
+        }
+    }
+}
+
+/// Stream a job's status changes as Server-Sent Events until it completes, fails
+/// or is cancelled. Each event is named after the status and carries the same
+/// JSON body as `/api/job-status/{job_id}`.
+pub async fn job_events(
+    path: web::Path<String>,
+    job_store: web::Data<JobStore>,
+    render_queue: web::Data<RenderQueue>,
+) -> Result<HttpResponse, Error> {
+    let job_id = path.into_inner();
+    if !is_valid_job_id(&job_id) {
+        return Err(actix_web::error::ErrorBadRequest("Invalid job ID"));
+    }
+
+    // Subscribe before reading the current status so no change falls in between
+    let events = job_store.subscribe();
+    let status = job_store.status(&job_id)
+        .ok_or_else(|| actix_web::error::ErrorNotFound("Job not found"))?;
+
+    let mut keepalive = interval(KEEPALIVE_INTERVAL);
+    keepalive.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    // The first tick fires immediately; the initial status takes its place
+    keepalive.reset();
+
+    let mut state = EventStream {
+        job_id,
+        events,
+        job_store,
+        render_queue,
+        keepalive,
+        last_sent: None,
+        last_was_queued: false,
+        finished: false,
+    };
+    let initial = state.message(&status);
+
+    let initial = stream::iter(initial.map(Ok::<_, Error>));
+    let updates = stream::unfold(state, |mut state| async move {
+        let message = state.next().await?;
+        Some((Ok::<_, Error>(message), state))
+    });
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .append_header(("Cache-Control", "no-cache"))
+        // Stop nginx and similar proxies from buffering the stream
+        .append_header(("X-Accel-Buffering", "no"))
+        .streaming(initial.chain(updates)))
+}
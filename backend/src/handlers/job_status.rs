@@ -1,45 +1,25 @@
 use actix_web::{web, HttpResponse, Error};
-use std::sync::{Arc, Mutex};
-use std::collections::HashMap;
 use crate::models::{JobStatus, JobStatusType};
-
-type JobStore = Arc<Mutex<HashMap<String, JobStatusType>>>;
+use crate::storage::job_store::JobStore;
+use crate::storage::local::is_valid_job_id;
+use crate::video::queue::RenderQueue;
 
 pub async fn get_job_status(
     path: web::Path<String>,
     job_store: web::Data<JobStore>,
+    render_queue: web::Data<RenderQueue>,
 ) -> Result<HttpResponse, Error> {
     let job_id = path.into_inner();
-    let store = job_store.lock().unwrap();
-    
-    let status = store.get(&job_id)
-        .cloned()
-        .unwrap_or(JobStatusType::Pending);
-    
-    let (status_str, error) = match &status {
-        JobStatusType::Failed(err) => ("failed", Some(err.clone())),
-        _ => (status.as_str(), None),
-    };
+    if !is_valid_job_id(&job_id) {
+        return Err(actix_web::error::ErrorBadRequest("Invalid job ID"));
+    }
+    let record = job_store.get(&job_id)
+        .ok_or_else(|| actix_web::error::ErrorNotFound("Job not found"))?;
 
-    // Extract progress data from status
-    let (progress, stage, current_frame, total_frames) = match &status {
-        JobStatusType::Processing(Some(p)) => (
-            Some(p.percent as u32),
-            Some(p.stage.clone()),
-            Some(p.current_frame),
-            Some(p.total_frames),
-        ),
-        JobStatusType::Processing(None) => (Some(0), Some("preparing".to_string()), None, None),
-        JobStatusType::Completed => (Some(100), Some("complete".to_string()), None, None),
-        _ => (None, None, None, None),
+    let queue_position = match &record.status {
+        JobStatusType::Queued => render_queue.position(&job_id),
+        _ => None,
     };
 
-    Ok(HttpResponse::Ok().json(JobStatus {
-        status: status_str.to_string(),
-        progress,
-        stage,
-        current_frame,
-        total_frames,
-        error,
-    }))
+    Ok(HttpResponse::Ok().json(JobStatus::new(&record.status, queue_position, record.settings.as_ref())))
 }
@@ -1,6 +1,13 @@
 pub mod upload;
+pub mod resumable_upload;
+pub mod archive_upload;
 pub mod preview;
+pub mod render_preview;
 pub mod create_timelapse;
 pub mod job_status;
+pub mod job_events;
+pub mod cancel_render;
+pub mod contact_sheet;
+pub mod pin;
 pub mod download;
 pub mod health;
@@ -0,0 +1,32 @@
+use actix_web::{web, HttpResponse, Error};
+use crate::models::PinResponse;
+use crate::storage::job_store::JobStore;
+use crate::storage::local::is_valid_job_id;
+
+/// Exempt a job from automatic expiry
+pub async fn pin_job(
+    path: web::Path<String>,
+    job_store: web::Data<JobStore>,
+) -> Result<HttpResponse, Error> {
+    set_pinned(path.into_inner(), &job_store, true)
+}
+
+/// Return a job to the normal retention rules
+pub async fn unpin_job(
+    path: web::Path<String>,
+    job_store: web::Data<JobStore>,
+) -> Result<HttpResponse, Error> {
+    set_pinned(path.into_inner(), &job_store, false)
+}
+
+fn set_pinned(job_id: String, job_store: &JobStore, pinned: bool) -> Result<HttpResponse, Error> {
+    if !is_valid_job_id(&job_id) {
+        return Err(actix_web::error::ErrorBadRequest("Invalid job ID"));
+    }
+
+    if !job_store.set_pinned(&job_id, pinned) {
+        return Err(actix_web::error::ErrorNotFound("Job not found"));
+    }
+
+    Ok(HttpResponse::Ok().json(PinResponse { job_id, pinned }))
+}
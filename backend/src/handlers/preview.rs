@@ -1,54 +1,145 @@
-use actix_web::{web, HttpResponse, Error};
-use crate::storage::local::get_frames_directory;
+use std::collections::HashSet;
+use actix_files::NamedFile;
+use actix_web::http::header::{self, HeaderValue};
+use actix_web::{web, HttpRequest, HttpResponse, Error};
+use serde::Deserialize;
+use crate::frames::ordering::{order_frames, FrameOrder, FrameOrderCache};
+use crate::frames::thumbnail::{thumbnail, ThumbnailFormat, ThumbnailSpec};
+use crate::frames::validate::detect_format;
+use crate::storage::job_store::JobStore;
+use crate::storage::local::{get_frames_directory, get_thumbnails_directory, is_valid_job_id, list_image_files};
+
+#[derive(Debug, Deserialize)]
+pub struct PreviewQuery {
+    /// Overrides the order saved with the job's last render request
+    order: Option<FrameOrder>,
+    /// Thumbnail bounding box; the original frame is served without any of
+    /// `width`, `height` and `format`
+    width: Option<u32>,
+    height: Option<u32>,
+    format: Option<ThumbnailFormat>,
+}
+
+impl PreviewQuery {
+    fn thumbnail(&self) -> Option<ThumbnailSpec> {
+        if self.width.is_none() && self.height.is_none() && self.format.is_none() {
+            return None;
+        }
+        Some(ThumbnailSpec {
+            width: self.width,
+            height: self.height,
+            format: self.format.unwrap_or_default(),
+        })
+    }
+}
+
+/// Serve a frame or thumbnail with validators. The frame behind an index
+/// changes when the order does, so clients must revalidate every time, but
+/// an unchanged image costs a `304 Not Modified` instead of the whole file.
+pub fn serve_image(req: &HttpRequest, path: &std::path::Path, content_type: &str) -> Result<HttpResponse, Error> {
+    let file = NamedFile::open(path)?
+        .set_content_type(content_type.parse().map_err(actix_web::error::ErrorInternalServerError)?)
+        .disable_content_disposition();
+
+    let mut response = file.into_response(req);
+    response
+        .headers_mut()
+        .insert(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+    Ok(response)
+}
+
+/// The job's frames in the order previews use: `order` if given, otherwise
+/// the order of the job's last render request. Frames a saved explicit order
+/// doesn't name come last, so every frame has an index
+pub async fn preview_frames(
+    job_id: &str,
+    order: Option<FrameOrder>,
+    job_store: &JobStore,
+    frame_order_cache: web::Data<FrameOrderCache>,
+) -> Result<Vec<String>, Error> {
+    let frames_dir = get_frames_directory(job_id);
+    let settings = job_store.get(job_id).and_then(|record| record.settings);
+    let (order, explicit_order) = match (order, settings) {
+        (Some(order), _) => (order, None),
+        (None, Some(settings)) => (settings.order, settings.explicit_order),
+        (None, None) => (FrameOrder::default(), None),
+    };
+
+    let job_id = job_id.to_string();
+    let files = web::block(move || {
+        let compute = || {
+            let files = list_image_files(&job_id)?;
+            let mut ordered = order_frames(&frames_dir, files.clone(), order, explicit_order.as_deref())?;
+            // Frames added after an explicit order was saved follow it in filename order
+            if order == FrameOrder::Explicit && ordered.len() < files.len() {
+                let listed: HashSet<&String> = ordered.iter().collect();
+                let unlisted = files.iter().filter(|f| !listed.contains(f)).cloned().collect();
+                ordered.extend(order_frames(&frames_dir, unlisted, FrameOrder::Filename, None)?);
+            }
+            Ok(ordered)
+        };
+        // Explicit orders come from the request and are cheap, so they aren't cached
+        if order == FrameOrder::Explicit {
+            compute()
+        } else {
+            frame_order_cache.get_or_compute(&job_id, order, &frames_dir, compute)
+        }
+    })
+    .await
+    .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    files.map_err(actix_web::error::ErrorBadRequest)
+}
 
 pub async fn get_preview(
+    req: HttpRequest,
     path: web::Path<(String, usize)>,
+    query: web::Query<PreviewQuery>,
+    job_store: web::Data<JobStore>,
+    frame_order_cache: web::Data<FrameOrderCache>,
 ) -> Result<HttpResponse, Error> {
     let (job_id, index) = path.into_inner();
+    if !is_valid_job_id(&job_id) {
+        return Err(actix_web::error::ErrorBadRequest("Invalid job ID"));
+    }
+    let thumbnail_spec = query.thumbnail();
+    if let Some(spec) = &thumbnail_spec {
+        spec.validate().map_err(actix_web::error::ErrorBadRequest)?;
+    }
+
     let frames_dir = get_frames_directory(&job_id);
-    
-    // List files and get the one at the specified index
-    let mut files: Vec<String> = std::fs::read_dir(&frames_dir)
-        .map_err(|_| actix_web::error::ErrorNotFound("Job not found"))?
-        .filter_map(|entry| {
-            entry.ok().and_then(|e| {
-                e.path().file_name()?.to_str().map(|s| s.to_string())
-            })
-        })
-        .collect();
-    
-    // Natural sort
-    files.sort_by(|a, b| alphanumeric_sort::compare_str(a, b));
-    
+    if !frames_dir.exists() {
+        return Err(actix_web::error::ErrorNotFound("Job not found"));
+    }
+
+    // Show frames in the same order the render will use
+    let files = preview_frames(&job_id, query.order, &job_store, frame_order_cache).await?;
+    let thumbnails_dir = get_thumbnails_directory(&job_id);
+
     if index >= files.len() {
         return Err(actix_web::error::ErrorNotFound("Index out of range"));
     }
-    
+
     let filepath = frames_dir.join(&files[index]);
-    
+
     if !filepath.exists() {
         return Err(actix_web::error::ErrorNotFound("File not found"));
     }
-    
-    let file_data = std::fs::read(&filepath)
-        .map_err(actix_web::error::ErrorInternalServerError)?;
-    
-    // Determine content type from extension
-    let content_type = if filepath.extension()
-        .and_then(|ext| ext.to_str())
-        .map(|ext| ext.to_lowercase())
-        .as_deref() == Some("png") {
-        "image/png"
-    } else if filepath.extension()
-        .and_then(|ext| ext.to_str())
-        .map(|ext| ext.to_lowercase())
-        .as_deref() == Some("webp") {
-        "image/webp"
-    } else {
-        "image/jpeg"
-    };
-    
-    Ok(HttpResponse::Ok()
-        .content_type(content_type)
-        .body(file_data))
+
+    if let Some(spec) = thumbnail_spec {
+        // Decoding and resizing a full-size frame is slow, so keep it off the async workers
+        let filename = files[index].clone();
+        let thumbnail_path = web::block(move || thumbnail(&frames_dir, &filename, &thumbnails_dir, &spec))
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+        return serve_image(&req, &thumbnail_path, spec.format.mime_type());
+    }
+
+    // Determine content type from the file's magic bytes
+    let content_type = detect_format(&filepath)
+        .map(|format| format.mime_type())
+        .unwrap_or("image/jpeg");
+
+    serve_image(&req, &filepath, content_type)
 }
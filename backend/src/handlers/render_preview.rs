@@ -0,0 +1,150 @@
+use std::path::PathBuf;
+use actix_web::http::header::{self, HeaderValue};
+use actix_web::{web, HttpResponse, Error};
+use anyhow::Context;
+use serde::Deserialize;
+use crate::frames::validate::validate_frame;
+use crate::handlers::create_timelapse::validate_settings;
+use crate::models::CreateTimelapseRequest;
+use crate::storage::local::{get_frames_directory, get_job_directory, is_valid_job_id};
+use crate::video::deflicker::deflicker_frame;
+use crate::video::filters::validate_crop_bounds;
+use crate::video::pacing::{fit_to_duration, step_frames};
+use crate::video::processor::{frames_for_render, render_still};
+use crate::video::rendition::{max_output_fps, render_outputs, validate_interpolation_fps};
+
+#[derive(Debug, Deserialize)]
+pub struct RenderPreviewQuery {
+    /// Rendition whose output settings to preview; the first one by default
+    rendition: Option<String>,
+}
+
+/// Render the frame at `index` of the video the request would produce, through
+/// the same selection, frame step, deflicker and filter chain as the render.
+/// Takes the same body as `POST /api/create-timelapse` and answers with a PNG.
+pub async fn render_preview(
+    path: web::Path<(String, usize)>,
+    query: web::Query<RenderPreviewQuery>,
+    req: web::Json<CreateTimelapseRequest>,
+) -> Result<HttpResponse, Error> {
+    let (job_id, index) = path.into_inner();
+    if !is_valid_job_id(&job_id) {
+        return Err(actix_web::error::ErrorBadRequest("Invalid job ID"));
+    }
+    let mut settings = req.into_inner();
+
+    if settings.job_id != job_id {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "jobId doesn't match the job in the URL"
+        })));
+    }
+    if let Err(e) = validate_settings(&settings) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": e
+        })));
+    }
+
+    // Previews show the video itself, not its draft
+    settings.draft = None;
+
+    let frames_dir = get_frames_directory(&job_id);
+    if !frames_dir.exists() {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Job not found"
+        })));
+    }
+
+    // Pick the frames the video would show, so the index matches its frame numbers
+    let frames = {
+        let job_id = job_id.clone();
+        let frames_dir = frames_dir.clone();
+        let settings = settings.clone();
+        web::block(move || {
+            let frames = frames_for_render(&job_id, &frames_dir, &settings)?;
+            if let Some(first) = frames.first() {
+                validate_crop_bounds(&settings, &frames_dir.join(first)).map_err(anyhow::Error::msg)?;
+            }
+            Ok::<_, anyhow::Error>(frames)
+        })
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+    };
+    let frames = match frames {
+        Ok(frames) => frames,
+        Err(e) => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": e.to_string()
+            })));
+        }
+    };
+    if let Some(target) = settings.target_duration_seconds {
+        match fit_to_duration(frames.len(), &settings, target, max_output_fps(&settings)) {
+            Ok(fit) => {
+                settings.fps = fit.fps;
+                settings.frame_step = fit.frame_step;
+            }
+            Err(e) => {
+                return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                    "error": e
+                })));
+            }
+        }
+        if let Err(e) = validate_interpolation_fps(&settings) {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": e
+            })));
+        }
+    }
+    let frame_paths: Vec<PathBuf> = step_frames(frames, settings.frame_step)
+        .iter()
+        .map(|filename| frames_dir.join(filename))
+        .collect();
+    if index >= frame_paths.len() {
+        return Err(actix_web::error::ErrorNotFound("Index out of range"));
+    }
+    if let Err(reason) = validate_frame(&frame_paths[index]) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": reason
+        })));
+    }
+
+    let outputs = render_outputs(&settings);
+    let output = match &query.rendition {
+        Some(id) => outputs.into_iter().find(|(rendition, _)| rendition.as_deref() == Some(id.as_str())),
+        None => outputs.into_iter().next(),
+    };
+    let Some((_, output_settings)) = output else {
+        return Err(actix_web::error::ErrorNotFound("Rendition not found"));
+    };
+
+    // A corrected copy goes into a temporary directory that is removed once
+    // the preview has been rendered
+    let (_deflicker_dir, frame_path) = match settings.deflicker.clone() {
+        Some(deflicker) => {
+            let job_dir = get_job_directory(&job_id);
+            let (dir, path) = web::block(move || {
+                let dir = tempfile::Builder::new()
+                    .prefix("preview")
+                    .tempdir_in(job_dir)
+                    .context("Failed to create deflicker directory")?;
+                let path = deflicker_frame(&frame_paths, index, dir.path(), &deflicker)?;
+                Ok::<_, anyhow::Error>((dir, path))
+            })
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+            (Some(dir), path)
+        }
+        None => (None, frame_paths[index].clone()),
+    };
+
+    let image = render_still(&frame_path, &output_settings)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    // The image depends on the request body, so it can't be revalidated or reused
+    Ok(HttpResponse::Ok()
+        .content_type("image/png")
+        .insert_header((header::CACHE_CONTROL, HeaderValue::from_static("no-store")))
+        .body(image))
+}
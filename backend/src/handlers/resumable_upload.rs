@@ -0,0 +1,252 @@
+use actix_web::{web, HttpRequest, HttpResponse, Error};
+use futures_util::StreamExt;
+use std::fs::OpenOptions;
+use std::io::Write;
+use uuid::Uuid;
+use crate::frames::validate::validate_frame;
+use crate::models::{CreateUploadRequest, JobStatusType, RejectedFile, UploadSessionResponse};
+use crate::config::Config;
+use crate::storage::job_store::{JobStore, UploadRefused};
+use crate::storage::local::{ensure_job_directory, get_frames_directory, is_supported_image, is_valid_job_id};
+use crate::storage::uploads::{complete_upload, create_upload, discard_upload, load_upload, ActiveUploads, UploadSession};
+
+const UPLOAD_OFFSET: &str = "Upload-Offset";
+const UPLOAD_LENGTH: &str = "Upload-Length";
+
+fn session_response(session: &UploadSession) -> UploadSessionResponse {
+    UploadSessionResponse {
+        upload_id: session.upload_id.clone(),
+        job_id: session.job_id.clone(),
+        filename: session.filename.clone(),
+        offset: session.offset(),
+        size: session.size,
+        complete: session.complete,
+        location: format!("/api/uploads/{}/{}", session.job_id, session.upload_id),
+    }
+}
+
+fn find_session(job_id: &str, upload_id: &str) -> Result<UploadSession, Error> {
+    load_upload(job_id, upload_id)
+        .map_err(actix_web::error::ErrorInternalServerError)?
+        .ok_or_else(|| actix_web::error::ErrorNotFound("Upload not found"))
+}
+
+/// Start a resumable upload of one frame, creating a new job unless `jobId` is given
+pub async fn create_upload_session(
+    req: web::Json<CreateUploadRequest>,
+    job_store: web::Data<JobStore>,
+    config: web::Data<Config>,
+) -> Result<HttpResponse, Error> {
+    if !is_supported_image(&req.filename) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Unsupported file type. Must be PNG, JPEG or WebP"
+        })));
+    }
+
+    if req.size == 0 {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Upload size must be greater than zero"
+        })));
+    }
+
+    let max_size = config.upload_max_file_gb.saturating_mul(1024 * 1024 * 1024);
+    if req.size > max_size {
+        return Ok(HttpResponse::PayloadTooLarge().json(serde_json::json!({
+            "error": format!("Uploads can be at most {} GB", config.upload_max_file_gb)
+        })));
+    }
+
+    let job_id = match &req.job_id {
+        Some(job_id) => {
+            if !is_valid_job_id(job_id) {
+                return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                    "error": "Invalid job ID"
+                })));
+            }
+            let Some(status) = job_store.status(job_id) else {
+                return Ok(HttpResponse::NotFound().json(serde_json::json!({
+                    "error": "Job not found"
+                })));
+            };
+            if !get_frames_directory(job_id).exists() {
+                return Ok(HttpResponse::NotFound().json(serde_json::json!({
+                    "error": "Job not found"
+                })));
+            }
+            // The frame list is read when a render starts, so don't change it underneath one
+            if matches!(status, JobStatusType::Queued | JobStatusType::Processing(_)) {
+                return Ok(HttpResponse::Conflict().json(serde_json::json!({
+                    "error": "Job is queued or rendering"
+                })));
+            }
+            // Adding frames restarts the job's retention
+            job_store.touch(job_id);
+            job_id.clone()
+        }
+        None => {
+            let job_id = Uuid::new_v4().to_string();
+            ensure_job_directory(&job_id)
+                .map_err(actix_web::error::ErrorInternalServerError)?;
+            job_store.create_job(&job_id, 0);
+            job_id
+        }
+    };
+
+    // Never let a finished upload replace a frame that is already there
+    let filename = sanitize_filename::sanitize(&req.filename);
+    if get_frames_directory(&job_id).join(&filename).exists() {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": format!("A frame named {} already exists in this job", filename)
+        })));
+    }
+
+    let size = req.size;
+    let session = web::block(move || create_upload(&job_id, &filename, size))
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let response = session_response(&session);
+    Ok(HttpResponse::Created()
+        .append_header(("Location", response.location.clone()))
+        .append_header((UPLOAD_OFFSET, response.offset.to_string()))
+        .json(response))
+}
+
+/// Current offset of an upload, so a client can resume after a dropped connection
+pub async fn get_upload_offset(
+    path: web::Path<(String, String)>,
+) -> Result<HttpResponse, Error> {
+    let (job_id, upload_id) = path.into_inner();
+    if !is_valid_job_id(&job_id) {
+        return Err(actix_web::error::ErrorBadRequest("Invalid job ID"));
+    }
+    let session = find_session(&job_id, &upload_id)?;
+
+    Ok(HttpResponse::Ok()
+        .append_header((UPLOAD_OFFSET, session.offset().to_string()))
+        .append_header((UPLOAD_LENGTH, session.size.to_string()))
+        .append_header(("Cache-Control", "no-store"))
+        .json(session_response(&session)))
+}
+
+/// Append a chunk at the offset given in the `Upload-Offset` header
+pub async fn patch_upload(
+    path: web::Path<(String, String)>,
+    req: HttpRequest,
+    mut payload: web::Payload,
+    job_store: web::Data<JobStore>,
+    active_uploads: web::Data<ActiveUploads>,
+) -> Result<HttpResponse, Error> {
+    let (job_id, upload_id) = path.into_inner();
+    if !is_valid_job_id(&job_id) {
+        return Err(actix_web::error::ErrorBadRequest("Invalid job ID"));
+    }
+
+    // One request at a time per upload, so the offset check below and the
+    // append that follows it can't interleave with another request's
+    let Some(_active_upload) = active_uploads.try_lock(&upload_id) else {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Another request is writing to this upload"
+        })));
+    };
+    let mut session = find_session(&job_id, &upload_id)?;
+
+    // No render may start while the chunk is written and the frame completed
+    let _uploading = match job_store.begin_upload(&job_id) {
+        Ok(guard) => guard,
+        Err(UploadRefused::UnknownJob) => return Err(actix_web::error::ErrorNotFound("Job not found")),
+        Err(UploadRefused::Busy) => {
+            return Ok(HttpResponse::Conflict()
+                .append_header((UPLOAD_OFFSET, session.offset().to_string()))
+                .json(serde_json::json!({
+                    "error": "Job is queued or rendering"
+                })));
+        }
+    };
+
+    // Keep a job that is still receiving frames from expiring mid-upload
+    job_store.touch(&job_id);
+
+    let client_offset = req.headers()
+        .get(UPLOAD_OFFSET)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok())
+        .ok_or_else(|| actix_web::error::ErrorBadRequest("Missing or invalid Upload-Offset header"))?;
+
+    // Chunks must continue exactly where the stored data ends
+    let mut offset = session.offset();
+    if session.complete || client_offset != offset {
+        return Ok(HttpResponse::Conflict()
+            .append_header((UPLOAD_OFFSET, offset.to_string()))
+            .json(serde_json::json!({
+                "error": "Upload-Offset does not match the current upload offset"
+            })));
+    }
+
+    let data_path = session.data_path();
+    let mut file = web::block(move || OpenOptions::new().append(true).open(data_path))
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    // Write chunks as they arrive; whatever made it to disk counts if the connection drops
+    while let Some(chunk) = payload.next().await {
+        let chunk = chunk?;
+        if offset + chunk.len() as u64 > session.size {
+            return Ok(HttpResponse::BadRequest()
+                .append_header((UPLOAD_OFFSET, offset.to_string()))
+                .json(serde_json::json!({
+                    "error": "Chunk exceeds the declared upload size"
+                })));
+        }
+
+        offset += chunk.len() as u64;
+        file = web::block(move || {
+            file.write_all(&chunk)?;
+            Ok::<_, std::io::Error>(file)
+        })
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    }
+    drop(file);
+
+    if offset == session.size {
+        let (completed, completed_session) = web::block(move || {
+            complete_upload(&mut session).map(|completed| (completed, session))
+        })
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+        session = completed_session;
+
+        // Another frame took the name while this one was uploading
+        if !completed {
+            discard_upload(&session);
+            return Ok(HttpResponse::Conflict().json(serde_json::json!({
+                "error": format!("A frame named {} already exists in this job", session.filename)
+            })));
+        }
+
+        // Only now is the whole file there to check its content
+        let frame_path = session.frame_path();
+        let validation = web::block(move || validate_frame(&frame_path))
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+        if let Err(reason) = validation {
+            discard_upload(&session);
+            return Ok(HttpResponse::UnprocessableEntity().json(serde_json::json!({
+                "error": "Uploaded file is not a valid image",
+                "rejected": RejectedFile {
+                    filename: session.filename.clone(),
+                    reason,
+                },
+            })));
+        }
+    }
+
+    Ok(HttpResponse::NoContent()
+        .append_header((UPLOAD_OFFSET, session.offset().to_string()))
+        .finish())
+}
@@ -1,48 +1,72 @@
 use actix_multipart::Multipart;
 use actix_web::{web, HttpResponse, Error};
 use futures_util::TryStreamExt;
-use std::fs::File;
-use std::io::Write;
+use std::fs::OpenOptions;
+use std::io::{ErrorKind, Write};
+use std::path::Path;
 use uuid::Uuid;
-use crate::models::UploadResponse;
-use crate::storage::local::{ensure_job_directory, get_frames_directory};
+use crate::frames::validate::validate_frame;
+use crate::frames::ordering::FrameOrderCache;
+use crate::handlers::preview::preview_frames;
+use crate::models::{AppendFramesResponse, RejectedFile, UploadResponse};
+use crate::storage::job_store::{JobStore, UploadRefused};
+use crate::storage::local::{ensure_job_directory, get_frames_directory, is_supported_image, is_valid_job_id, list_image_files};
+
+/// Frames written from one multipart request
+struct SavedFrames {
+    filenames: Vec<String>,
+    /// Files skipped because a frame with the same name already exists
+    collisions: Vec<String>,
+    /// Files skipped because they aren't valid images
+    rejected: Vec<RejectedFile>,
+}
+
+/// Stream every image field of a multipart request into `frames_dir`.
+///
+/// Existing frames are never overwritten; same-named files are reported as collisions.
+/// Each written file is checked by content, and removed again if it isn't a usable image.
+async fn save_frames(payload: &mut Multipart, frames_dir: &Path) -> Result<SavedFrames, Error> {
+    let mut saved = SavedFrames {
+        filenames: Vec::new(),
+        collisions: Vec::new(),
+        rejected: Vec::new(),
+    };
 
-pub async fn upload_files(mut payload: Multipart) -> Result<HttpResponse, Error> {
-    let job_id = Uuid::new_v4().to_string();
-    ensure_job_directory(&job_id)
-        .map_err(actix_web::error::ErrorInternalServerError)?;
-    
-    let frames_dir = get_frames_directory(&job_id);
-    let mut file_count = 0;
-    let mut filenames = Vec::new();
-    
     // Process each file in the multipart stream
     while let Ok(Some(mut field)) = payload.try_next().await {
         let content_disposition = field.content_disposition();
-        
+
         if let Some(filename) = content_disposition.get_filename() {
             // Validate file extension
-            let ext = filename
-                .rsplit('.')
-                .next()
-                .unwrap_or("")
-                .to_lowercase();
-            
-            if !matches!(ext.as_str(), "png" | "jpg" | "jpeg" | "webp") {
-                continue; // Skip non-image files
+            if !is_supported_image(filename) {
+                saved.rejected.push(RejectedFile {
+                    filename: filename.to_string(),
+                    reason: "Unsupported file extension".to_string(),
+                });
+                continue;
             }
-            
+
             // Sanitize filename
             let sanitized_filename = sanitize_filename::sanitize(filename);
             let filepath = frames_dir.join(&sanitized_filename);
-            
-            // Create file and write chunks
+
+            // Create file (failing if it already exists) and write chunks
             let filepath_clone = filepath.clone();
-            let mut file = web::block(move || File::create(&filepath_clone))
-                .await
-                .map_err(actix_web::error::ErrorInternalServerError)?
-                .map_err(actix_web::error::ErrorInternalServerError)?;
-            
+            let created = web::block(move || {
+                OpenOptions::new().write(true).create_new(true).open(&filepath_clone)
+            })
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+
+            let mut file = match created {
+                Ok(file) => file,
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                    saved.collisions.push(sanitized_filename);
+                    continue;
+                }
+                Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+            };
+
             // Write file in chunks
             while let Ok(Some(chunk)) = field.try_next().await {
                 file = web::block(move || {
@@ -53,21 +77,114 @@ pub async fn upload_files(mut payload: Multipart) -> Result<HttpResponse, Error>
                 .map_err(actix_web::error::ErrorInternalServerError)?
                 .map_err(actix_web::error::ErrorInternalServerError)?;
             }
+            drop(file);
+
+            // Check the content really is the image its extension claims
+            let validation = web::block(move || {
+                let result = validate_frame(&filepath);
+                if result.is_err() {
+                    let _ = std::fs::remove_file(&filepath);
+                }
+                result
+            })
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
 
-            file_count += 1;
-            filenames.push(sanitized_filename);
+            match validation {
+                Ok(_) => saved.filenames.push(sanitized_filename),
+                Err(reason) => saved.rejected.push(RejectedFile {
+                    filename: sanitized_filename,
+                    reason,
+                }),
+            }
         }
     }
-    
+
+    Ok(saved)
+}
+
+pub async fn upload_files(
+    mut payload: Multipart,
+    job_store: web::Data<JobStore>,
+) -> Result<HttpResponse, Error> {
+    let job_id = Uuid::new_v4().to_string();
+    ensure_job_directory(&job_id)
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let frames_dir = get_frames_directory(&job_id);
+    let saved = save_frames(&mut payload, &frames_dir).await?;
+    let file_count = saved.filenames.len();
+
     if file_count == 0 {
         return Ok(HttpResponse::BadRequest().json(serde_json::json!({
-            "error": "No valid image files uploaded"
+            "error": "No valid image files uploaded",
+            "rejected": saved.rejected,
         })));
     }
-    
+
+    job_store.create_job(&job_id, file_count);
+
     Ok(HttpResponse::Ok().json(UploadResponse {
         job_id,
         file_count,
-        filenames,
+        filenames: saved.filenames,
+        collisions: saved.collisions,
+        rejected: saved.rejected,
+    }))
+}
+
+/// Upload additional frames into an existing job
+pub async fn append_frames(
+    path: web::Path<String>,
+    mut payload: Multipart,
+    job_store: web::Data<JobStore>,
+    frame_order_cache: web::Data<FrameOrderCache>,
+) -> Result<HttpResponse, Error> {
+    let job_id = path.into_inner();
+    if !is_valid_job_id(&job_id) {
+        return Err(actix_web::error::ErrorBadRequest("Invalid job ID"));
+    }
+    let frames_dir = get_frames_directory(&job_id);
+
+    if !frames_dir.exists() {
+        return Err(actix_web::error::ErrorNotFound("Job not found"));
+    }
+
+    // The frame list is read when a render starts, so don't change it underneath
+    // one, and keep renders from being queued until every frame is written
+    let uploading = match job_store.begin_upload(&job_id) {
+        Ok(guard) => guard,
+        Err(UploadRefused::UnknownJob) => return Err(actix_web::error::ErrorNotFound("Job not found")),
+        Err(UploadRefused::Busy) => {
+            return Ok(HttpResponse::Conflict().json(serde_json::json!({
+                "error": "Job is queued or rendering"
+            })));
+        }
+    };
+
+    // Appending can take a while and restarts the job's retention
+    job_store.touch(&job_id);
+    let saved = save_frames(&mut payload, &frames_dir).await?;
+    job_store.touch(&job_id);
+    drop(uploading);
+
+    // List the frames the way previews number them, in the job's saved order
+    let frames = preview_frames(&job_id, None, &job_store, frame_order_cache).await?;
+    let frame_count = {
+        let job_id = job_id.clone();
+        web::block(move || list_image_files(&job_id))
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?
+            .map_err(actix_web::error::ErrorInternalServerError)?
+            .len()
+    };
+
+    Ok(HttpResponse::Ok().json(AppendFramesResponse {
+        job_id,
+        added: saved.filenames,
+        collisions: saved.collisions,
+        rejected: saved.rejected,
+        frame_count,
+        frames,
     }))
 }
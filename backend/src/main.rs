@@ -1,39 +1,85 @@
 use actix_web::{web, App, HttpServer};
 use actix_multipart::form::MultipartFormConfig;
 use actix_cors::Cors;
-use std::sync::{Arc, Mutex};
-use std::collections::HashMap;
+use std::time::Duration;
+use crate::config::Config;
+use crate::frames::ordering::FrameOrderCache;
 use crate::models::JobStatusType;
+use crate::storage::job_store::JobStore;
+use crate::storage::local::get_job_store_path;
+use crate::storage::reaper::{self, Retention};
+use crate::storage::uploads::ActiveUploads;
+use crate::video::queue::RenderQueue;
 use crate::handlers::{
-    upload::upload_files,
+    upload::{upload_files, append_frames},
+    archive_upload::upload_archive,
+    resumable_upload::{create_upload_session, get_upload_offset, patch_upload},
     preview::get_preview,
+    render_preview::render_preview,
     create_timelapse::create_timelapse_handler,
     job_status::get_job_status,
-    download::download_video,
+    job_events::job_events,
+    cancel_render::cancel_render,
+    contact_sheet::get_contact_sheet,
+    pin::{pin_job, unpin_job},
+    download::{download_draft, download_rendition, download_video},
     health::health_check,
 };
 
+mod config;
+mod frames;
 mod models;
 mod handlers;
 mod storage;
 mod video;
 
-type JobStore = Arc<Mutex<HashMap<String, JobStatusType>>>;
-
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    // Initialize job store
-    let job_store: JobStore = Arc::new(Mutex::new(HashMap::new()));
+    // Load the persistent job store (marks interrupted renders as failed)
+    let job_store = JobStore::open(&get_job_store_path())
+        .map_err(|e| std::io::Error::other(format!("Failed to open job store: {:#}", e)))?;
     
+    // Start the render workers and put back anything that was waiting before a restart
+    let config = Config::from_env();
+    let render_queue = RenderQueue::start(
+        config.render_workers,
+        config.render_queue_capacity,
+        job_store.clone(),
+    );
+    for (job_id, settings) in job_store.queued_jobs() {
+        if render_queue.enqueue(&job_id, settings).is_err() {
+            job_store.set_status(
+                &job_id,
+                JobStatusType::Failed("Render queue was full after a backend restart".to_string()),
+            );
+        }
+    }
+
+    // Periodically delete expired job directories and their store entries
+    reaper::start(
+        job_store.clone(),
+        Retention::from_config(&config),
+        Duration::from_secs(config.reaper_interval_minutes * 60),
+    );
+
+    // Shared by all workers so preview orders are computed once per job
+    let frame_order_cache = FrameOrderCache::default();
+    let active_uploads = ActiveUploads::default();
+
     println!("Starting Timelapse Creator Backend on http://0.0.0.0:8080");
     
     HttpServer::new(move || {
         let job_store = web::Data::new(job_store.clone());
+        let render_queue = web::Data::new(render_queue.clone());
+        let config = web::Data::new(config.clone());
+        let frame_order_cache = web::Data::new(frame_order_cache.clone());
+        let active_uploads = web::Data::new(active_uploads.clone());
 
         let cors = Cors::default()
             .allow_any_origin()
             .allow_any_method()
             .allow_any_header()
+            .expose_any_header()
             .max_age(3600);
 
         // Configure payload limits (10GB total request size)
@@ -47,15 +93,33 @@ async fn main() -> std::io::Result<()> {
         App::new()
             .wrap(cors)
             .app_data(job_store.clone())
+            .app_data(render_queue.clone())
+            .app_data(config.clone())
+            .app_data(frame_order_cache.clone())
+            .app_data(active_uploads.clone())
             .app_data(payload_config)
             .app_data(multipart_config)
             .service(
                 web::scope("/api")
                     .route("/upload", web::post().to(upload_files))
+                    .route("/upload/{job_id}", web::post().to(append_frames))
+                    .route("/upload-archive", web::post().to(upload_archive))
+                    .route("/uploads", web::post().to(create_upload_session))
+                    .route("/uploads/{job_id}/{upload_id}", web::head().to(get_upload_offset))
+                    .route("/uploads/{job_id}/{upload_id}", web::get().to(get_upload_offset))
+                    .route("/uploads/{job_id}/{upload_id}", web::patch().to(patch_upload))
                     .route("/preview/{job_id}/{index}", web::get().to(get_preview))
+                    .route("/preview/{job_id}/{index}", web::post().to(render_preview))
                     .route("/create-timelapse", web::post().to(create_timelapse_handler))
                     .route("/job-status/{job_id}", web::get().to(get_job_status))
+                    .route("/jobs/{job_id}/events", web::get().to(job_events))
+                    .route("/jobs/{job_id}/render", web::delete().to(cancel_render))
+                    .route("/jobs/{job_id}/pin", web::put().to(pin_job))
+                    .route("/jobs/{job_id}/pin", web::delete().to(unpin_job))
+                    .route("/jobs/{job_id}/contact-sheet", web::get().to(get_contact_sheet))
                     .route("/download/{job_id}", web::get().to(download_video))
+                    .route("/download/{job_id}/{rendition_id}", web::get().to(download_rendition))
+                    .route("/draft/{job_id}", web::get().to(download_draft))
             )
             .route("/health", web::get().to(health_check))
     })
@@ -1,12 +1,36 @@
 use serde::{Deserialize, Serialize};
+use crate::frames::ordering::FrameOrder;
+use crate::frames::selection::FrameSelection;
+use crate::video::codec::{Container, VideoCodec};
+use crate::video::deflicker::Deflicker;
+use crate::video::draft::Draft;
+use crate::video::filters::{CropRect, FitMode, ResolutionPreset};
+use crate::video::interpolate::Interpolation;
+use crate::video::pacing::SlowMotion;
+use crate::video::rendition::Rendition;
+use crate::video::stabilize::Stabilize;
 
 #[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ProcessingProgress {
-    pub stage: String,           // "preparing", "encoding", "finalizing"
+    pub stage: String,           // "preparing", "deflickering", "analyzing", "encoding", "finalizing"
     pub current_frame: u32,
     pub total_frames: u32,
     pub percent: u8,
+    /// Estimated seconds until encoding finishes, once enough frames have been encoded
+    pub eta_seconds: Option<u64>,
+    /// Rendition being encoded, for renders that produce several
+    pub rendition: Option<String>,
+    /// Renditions of this render already written
+    pub completed_renditions: Vec<String>,
+}
+
+/// A file that was not stored as a frame, and why
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RejectedFile {
+    pub filename: String,
+    pub reason: String,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -15,14 +39,124 @@ pub struct UploadResponse {
     pub job_id: String,
     pub file_count: usize,
     pub filenames: Vec<String>,
+    /// Files skipped because a frame with the same name was already uploaded
+    pub collisions: Vec<String>,
+    /// Files skipped because they aren't valid images
+    pub rejected: Vec<RejectedFile>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AppendFramesResponse {
+    pub job_id: String,
+    pub added: Vec<String>,
+    pub collisions: Vec<String>,
+    pub rejected: Vec<RejectedFile>,
+    pub frame_count: usize,
+    /// The job's frames in preview order, i.e. the order of its last render request
+    pub frames: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateUploadRequest {
+    /// Existing job to upload into; a new job is created when omitted
+    pub job_id: Option<String>,
+    pub filename: String,
+    pub size: u64,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
+pub struct UploadSessionResponse {
+    pub upload_id: String,
+    pub job_id: String,
+    pub filename: String,
+    pub offset: u64,
+    pub size: u64,
+    pub complete: bool,
+    pub location: String,
+}
+
+fn default_frame_step() -> usize {
+    1
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct CreateTimelapseRequest {
     pub job_id: String,
     pub rotation: u32,
-    pub fps: u32,
+    /// Frames per second; computed from `target_duration_seconds` when that is given
+    #[serde(default)]
+    pub fps: f64,
+    /// Desired video length; the backend picks `fps` and `frame_step` to match
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target_duration_seconds: Option<f64>,
+    /// Only every Nth frame is rendered
+    #[serde(default = "default_frame_step")]
+    pub frame_step: usize,
+    #[serde(default)]
+    pub order: FrameOrder,
+    /// Rules that pick which frames are rendered
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub selection: Option<FrameSelection>,
+    /// Frame filenames in render order, required when `order` is `explicit`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub explicit_order: Option<Vec<String>>,
+    #[serde(default)]
+    pub codec: VideoCodec,
+    /// Defaults to MP4 for H.264/H.265, WebM for VP9/AV1 and MOV for ProRes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub container: Option<Container>,
+    /// CRF value; lower is better quality. Not used by ProRes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quality: Option<u32>,
+    /// Encoder speed preset, or the profile for ProRes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preset: Option<String>,
+    /// Output size preset; mutually exclusive with `width`/`height`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resolution: Option<ResolutionPreset>,
+    /// Output width; with only one of `width`/`height` the other follows the aspect ratio
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub width: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub height: Option<u32>,
+    #[serde(default)]
+    pub fit: FitMode,
+    /// Part of the source frames to keep, applied before rotation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub crop: Option<CropRect>,
+    /// Frames shown slower at the start; off by default
+    #[serde(default = "SlowMotion::disabled")]
+    pub slow_start: SlowMotion,
+    /// Frames shown slower at the end; the last 5 at 2 fps by default
+    #[serde(default = "SlowMotion::default_ending")]
+    pub slow_ending: SlowMotion,
+    /// Extra seconds the last frame stays on screen
+    #[serde(default)]
+    pub hold_last_seconds: f64,
+    /// Even out brightness changes between frames before encoding
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deflicker: Option<Deflicker>,
+    /// Remove camera shake with a motion analysis pass before encoding
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stabilize: Option<Stabilize>,
+    /// Synthesize in-between frames up to a higher output frame rate
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interpolation: Option<Interpolation>,
+    /// Times a GIF or WebP animation plays; 0 or unset loops forever
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub loop_count: Option<u32>,
+    /// Produce several outputs, each with its own codec and size settings,
+    /// instead of one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub renditions: Option<Vec<Rendition>>,
+    /// Render a small, fast draft with the same pacing into its own file
+    /// instead of the video
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub draft: Option<Draft>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -30,17 +164,139 @@ pub struct CreateTimelapseRequest {
 pub struct CreateTimelapseResponse {
     pub job_id: String,
     pub status: String,
+    pub queue_position: Option<usize>,
+    /// Frame rate the video is rendered at
+    pub fps: f64,
+    /// Only every Nth frame is rendered
+    pub frame_step: usize,
+    /// Frames that end up in the video
+    pub frame_count: usize,
+    /// Expected length of the video in seconds
+    pub duration_seconds: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CancelRenderResponse {
+    pub job_id: String,
+    pub status: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PinResponse {
+    pub job_id: String,
+    pub pinned: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct JobStatus {
     pub status: String,
+    pub queue_position: Option<usize>,
     pub progress: Option<u32>,
     pub stage: Option<String>,
     pub current_frame: Option<u32>,
     pub total_frames: Option<u32>,
+    pub eta_seconds: Option<u64>,
     pub error: Option<String>,
+    /// Per-output status of a render that produces several
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub renditions: Option<Vec<RenditionStatus>>,
+    /// Set once a draft render has finished
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub draft_url: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RenditionStatus {
+    pub id: String,
+    /// "pending", "processing" or "completed", or the job's status once it
+    /// failed or was cancelled
+    pub status: String,
+    pub progress: u32,
+    pub eta_seconds: Option<u64>,
+    /// Set once the rendition has been written
+    pub download_url: Option<String>,
+}
+
+impl RenditionStatus {
+    fn new(job_id: &str, id: &str, job_status: &JobStatusType) -> Self {
+        let (status, progress, eta_seconds) = match job_status {
+            JobStatusType::Completed => ("completed", 100, None),
+            JobStatusType::Processing(Some(p)) if p.completed_renditions.iter().any(|r| r == id) => {
+                ("completed", 100, None)
+            }
+            JobStatusType::Processing(Some(p)) if p.rendition.as_deref() == Some(id) => {
+                ("processing", p.percent as u32, p.eta_seconds)
+            }
+            JobStatusType::Failed(_) | JobStatusType::Cancelled => (job_status.as_str(), 0, None),
+            _ => ("pending", 0, None),
+        };
+
+        Self {
+            id: id.to_string(),
+            status: status.to_string(),
+            progress,
+            eta_seconds,
+            download_url: (status == "completed")
+                .then(|| format!("/api/download/{}/{}", job_id, id)),
+        }
+    }
+}
+
+impl JobStatus {
+    /// Client-facing view of a job's status, given the settings it was last
+    /// rendered with
+    pub fn new(
+        status: &JobStatusType,
+        queue_position: Option<usize>,
+        settings: Option<&CreateTimelapseRequest>,
+    ) -> Self {
+        let error = match status {
+            JobStatusType::Failed(err) => Some(err.clone()),
+            _ => None,
+        };
+
+        // Extract progress data from status
+        let (progress, stage, current_frame, total_frames, eta_seconds) = match status {
+            JobStatusType::Processing(Some(p)) => (
+                Some(p.percent as u32),
+                Some(p.stage.clone()),
+                Some(p.current_frame),
+                Some(p.total_frames),
+                p.eta_seconds,
+            ),
+            JobStatusType::Processing(None) => (Some(0), Some("preparing".to_string()), None, None, None),
+            JobStatusType::Completed => (Some(100), Some("complete".to_string()), None, None, None),
+            _ => (None, None, None, None, None),
+        };
+
+        JobStatus {
+            status: status.as_str().to_string(),
+            queue_position,
+            progress,
+            stage,
+            current_frame,
+            total_frames,
+            eta_seconds,
+            error,
+            // A draft produces a single file whatever renditions the request asks for
+            renditions: settings.filter(|s| s.draft.is_none()).and_then(|s| {
+                let renditions = s.renditions.as_ref()?;
+                Some(renditions.iter().map(|r| RenditionStatus::new(&s.job_id, &r.id, status)).collect())
+            }),
+            draft_url: settings
+                .filter(|s| s.draft.is_some() && matches!(status, JobStatusType::Completed))
+                .map(|s| format!("/api/draft/{}", s.job_id)),
+        }
+    }
+
+    /// Completed, failed and cancelled jobs don't change until rendered again
+    pub fn is_final(&self) -> bool {
+        matches!(self.status.as_str(), "completed" | "failed" | "cancelled")
+    }
 }
 
 #[allow(dead_code)]
@@ -55,18 +311,22 @@ pub struct JobInfo {
 #[derive(Debug, Clone)]
 pub enum JobStatusType {
     Pending,
+    Queued,
     Processing(Option<ProcessingProgress>),
     Completed,
     Failed(String),
+    Cancelled,
 }
 
 impl JobStatusType {
     pub fn as_str(&self) -> &str {
         match self {
             JobStatusType::Pending => "pending",
+            JobStatusType::Queued => "queued",
             JobStatusType::Processing(_) => "processing",
             JobStatusType::Completed => "completed",
             JobStatusType::Failed(_) => "failed",
+            JobStatusType::Cancelled => "cancelled",
         }
     }
 }
@@ -0,0 +1,355 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, ErrorKind, Read};
+use std::path::{Path, PathBuf};
+use anyhow::{Result, Context};
+use flate2::read::GzDecoder;
+use crate::frames::validate::validate_frame;
+use crate::models::RejectedFile;
+use crate::storage::local::is_supported_image;
+
+/// Archive formats accepted for frame uploads
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArchiveKind {
+    Zip,
+    Tar,
+    TarGz,
+}
+
+impl ArchiveKind {
+    pub fn from_filename(filename: &str) -> Option<Self> {
+        let name = filename.to_lowercase();
+        if name.ends_with(".zip") {
+            Some(Self::Zip)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(Self::TarGz)
+        } else if name.ends_with(".tar") {
+            Some(Self::Tar)
+        } else {
+            None
+        }
+    }
+}
+
+/// Guards against decompression bombs
+#[derive(Debug, Clone)]
+pub struct ExtractLimits {
+    /// Maximum number of entries read from the archive, images or not
+    pub max_entries: usize,
+    /// Maximum total bytes written to the frames directory
+    pub max_total_bytes: u64,
+    /// Maximum ratio of extracted bytes to archive bytes
+    pub max_ratio: u64,
+}
+
+/// Frames written from one archive
+#[derive(Debug, Default)]
+pub struct ExtractedFrames {
+    pub filenames: Vec<String>,
+    /// Entries skipped because a frame with the same name already exists
+    pub collisions: Vec<String>,
+    /// Image entries skipped because their content isn't a valid image
+    pub rejected: Vec<RejectedFile>,
+}
+
+/// Extract the image entries of an archive into `frames_dir`.
+///
+/// Directory structure inside the archive is flattened to the entry's file name,
+/// so entries can never be written outside `frames_dir`. On error, every frame
+/// written so far is removed again.
+pub fn extract_archive(
+    archive_path: &Path,
+    kind: ArchiveKind,
+    frames_dir: &Path,
+    limits: &ExtractLimits,
+) -> Result<ExtractedFrames> {
+    let archive_size = fs::metadata(archive_path)
+        .context("Failed to read archive")?
+        .len();
+    let byte_budget = limits.max_total_bytes
+        .min(archive_size.saturating_mul(limits.max_ratio));
+
+    let mut extractor = Extractor {
+        frames_dir,
+        limits,
+        byte_budget,
+        entries: 0,
+        written_bytes: 0,
+        written_paths: Vec::new(),
+        result: ExtractedFrames::default(),
+    };
+
+    let file = File::open(archive_path).context("Failed to open archive")?;
+    let outcome = match kind {
+        ArchiveKind::Zip => extractor.extract_zip(file),
+        ArchiveKind::Tar => extractor.extract_tar(file),
+        ArchiveKind::TarGz => extractor.extract_tar(GzDecoder::new(file)),
+    };
+
+    if let Err(e) = outcome {
+        for path in &extractor.written_paths {
+            let _ = fs::remove_file(path);
+        }
+        return Err(e);
+    }
+
+    Ok(extractor.result)
+}
+
+struct Extractor<'a> {
+    frames_dir: &'a Path,
+    limits: &'a ExtractLimits,
+    byte_budget: u64,
+    entries: usize,
+    written_bytes: u64,
+    written_paths: Vec<PathBuf>,
+    result: ExtractedFrames,
+}
+
+impl Extractor<'_> {
+    fn extract_zip(&mut self, file: File) -> Result<()> {
+        let mut archive = zip::ZipArchive::new(file).context("Invalid ZIP archive")?;
+
+        for i in 0..archive.len() {
+            self.count_entry()?;
+            let entry = archive.by_index(i).context("Invalid ZIP entry")?;
+            if !entry.is_file() {
+                continue;
+            }
+            let name = entry.name().to_string();
+            self.write_entry(&name, entry)?;
+        }
+
+        Ok(())
+    }
+
+    fn extract_tar<R: Read>(&mut self, reader: R) -> Result<()> {
+        let mut archive = tar::Archive::new(reader);
+
+        for entry in archive.entries().context("Invalid TAR archive")? {
+            self.count_entry()?;
+            let entry = entry.context("Invalid TAR entry")?;
+            // Only regular files; links could point anywhere on disk
+            if !entry.header().entry_type().is_file() {
+                continue;
+            }
+            let name = entry.path()
+                .context("Invalid TAR entry name")?
+                .to_string_lossy()
+                .to_string();
+            self.write_entry(&name, entry)?;
+        }
+
+        Ok(())
+    }
+
+    fn count_entry(&mut self) -> Result<()> {
+        self.entries += 1;
+        if self.entries > self.limits.max_entries {
+            anyhow::bail!("Archive has more than {} entries", self.limits.max_entries);
+        }
+        Ok(())
+    }
+
+    /// Write one entry as a frame, keeping only its sanitized file name
+    fn write_entry<R: Read>(&mut self, entry_name: &str, reader: R) -> Result<()> {
+        let Some(base_name) = entry_name.rsplit(['/', '\\']).next() else {
+            return Ok(());
+        };
+        // Skip macOS resource forks and other hidden files
+        if base_name.is_empty() || base_name.starts_with('.') || !is_supported_image(base_name) {
+            return Ok(());
+        }
+
+        let filename = sanitize_filename::sanitize(base_name);
+        let path = self.frames_dir.join(&filename);
+
+        let mut file = match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                self.result.collisions.push(filename);
+                return Ok(());
+            }
+            Err(e) => return Err(e).context("Failed to create frame file"),
+        };
+        self.written_paths.push(path.clone());
+
+        // Count real decompressed bytes rather than trusting sizes in the headers
+        let remaining = self.byte_budget.saturating_sub(self.written_bytes);
+        let copied = io::copy(&mut reader.take(remaining + 1), &mut file)
+            .context("Failed to extract archive entry")?;
+        self.written_bytes += copied;
+        if self.written_bytes > self.byte_budget {
+            anyhow::bail!("Archive expands to more data than allowed");
+        }
+        drop(file);
+
+        match validate_frame(&path) {
+            Ok(_) => self.result.filenames.push(filename),
+            Err(reason) => {
+                let _ = fs::remove_file(&path);
+                self.result.rejected.push(RejectedFile { filename, reason });
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Write};
+    use image::{ImageFormat, RgbImage};
+    use tempfile::TempDir;
+    use zip::write::SimpleFileOptions;
+
+    fn png() -> Vec<u8> {
+        let mut bytes = Cursor::new(Vec::new());
+        RgbImage::new(4, 4).write_to(&mut bytes, ImageFormat::Png).unwrap();
+        bytes.into_inner()
+    }
+
+    fn limits() -> ExtractLimits {
+        ExtractLimits {
+            max_entries: 100,
+            max_total_bytes: 1 << 20,
+            max_ratio: 100,
+        }
+    }
+
+    /// A ZIP of `entries` in a new temporary directory, next to an empty frames directory
+    fn zip_archive(entries: &[(&str, Vec<u8>)]) -> (TempDir, PathBuf, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let archive_path = dir.path().join("frames.zip");
+        let mut writer = zip::ZipWriter::new(File::create(&archive_path).unwrap());
+        for (name, data) in entries {
+            writer.start_file(*name, SimpleFileOptions::default()).unwrap();
+            writer.write_all(data).unwrap();
+        }
+        writer.finish().unwrap();
+
+        let frames_dir = dir.path().join("job").join("frames");
+        fs::create_dir_all(&frames_dir).unwrap();
+        (dir, archive_path, frames_dir)
+    }
+
+    fn frame_names(frames_dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(frames_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn entries_are_flattened_into_the_frames_directory() {
+        let (dir, archive, frames_dir) = zip_archive(&[
+            ("../evil.png", png()),
+            ("../../job/escape.png", png()),
+            ("day1/frame.png", png()),
+            ("notes.txt", b"not a frame".to_vec()),
+            ("__MACOSX/._frame.png", png()),
+        ]);
+
+        let extracted = extract_archive(&archive, ArchiveKind::Zip, &frames_dir, &limits()).unwrap();
+
+        assert_eq!(frame_names(&frames_dir), ["escape.png", "evil.png", "frame.png"]);
+        assert_eq!(extracted.filenames.len(), 3);
+        assert!(!dir.path().join("evil.png").exists());
+        assert!(!dir.path().join("job").join("evil.png").exists());
+        assert!(!dir.path().join("job").join("escape.png").exists());
+    }
+
+    #[test]
+    fn tar_entries_are_flattened_into_the_frames_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive_path = dir.path().join("frames.tar");
+        let mut builder = tar::Builder::new(File::create(&archive_path).unwrap());
+        let frame = png();
+        // The builder refuses `..` in paths, so write the name into the header directly
+        let mut header = tar::Header::new_old();
+        let name = b"../evil.png";
+        header.as_old_mut().name[..name.len()].copy_from_slice(name);
+        header.set_entry_type(tar::EntryType::Regular);
+        header.set_size(frame.len() as u64);
+        header.set_cksum();
+        builder.append(&header, frame.as_slice()).unwrap();
+        builder.into_inner().unwrap();
+
+        let frames_dir = dir.path().join("job").join("frames");
+        fs::create_dir_all(&frames_dir).unwrap();
+        let extracted = extract_archive(&archive_path, ArchiveKind::Tar, &frames_dir, &limits()).unwrap();
+
+        assert_eq!(extracted.filenames, ["evil.png"]);
+        assert!(!dir.path().join("job").join("evil.png").exists());
+    }
+
+    #[test]
+    fn same_named_entries_are_reported_as_collisions() {
+        let (_dir, archive, frames_dir) = zip_archive(&[("a/frame.png", png()), ("b/frame.png", png())]);
+
+        let extracted = extract_archive(&archive, ArchiveKind::Zip, &frames_dir, &limits()).unwrap();
+
+        assert_eq!(extracted.filenames, ["frame.png"]);
+        assert_eq!(extracted.collisions, ["frame.png"]);
+    }
+
+    #[test]
+    fn entry_over_the_byte_budget_fails_and_removes_written_frames() {
+        let frame = png();
+        let (_dir, archive, frames_dir) = zip_archive(&[("first.png", frame.clone()), ("second.png", frame.clone())]);
+        let limits = ExtractLimits {
+            max_total_bytes: frame.len() as u64 + 1,
+            ..limits()
+        };
+
+        let err = extract_archive(&archive, ArchiveKind::Zip, &frames_dir, &limits).unwrap_err();
+
+        assert!(err.to_string().contains("more data than allowed"), "{}", err);
+        assert!(frame_names(&frames_dir).is_empty());
+    }
+
+    #[test]
+    fn entry_exactly_at_the_byte_budget_is_kept() {
+        let frame = png();
+        let (_dir, archive, frames_dir) = zip_archive(&[("first.png", frame.clone())]);
+        let limits = ExtractLimits {
+            max_total_bytes: frame.len() as u64,
+            ..limits()
+        };
+
+        let extracted = extract_archive(&archive, ArchiveKind::Zip, &frames_dir, &limits).unwrap();
+
+        assert_eq!(extracted.filenames, ["first.png"]);
+    }
+
+    #[test]
+    fn too_many_entries_fails_and_removes_written_frames() {
+        let (_dir, archive, frames_dir) = zip_archive(&[
+            ("1.png", png()),
+            ("2.png", png()),
+            ("readme.txt", b"skipped, but still counted".to_vec()),
+        ]);
+        let limits = ExtractLimits {
+            max_entries: 2,
+            ..limits()
+        };
+
+        let err = extract_archive(&archive, ArchiveKind::Zip, &frames_dir, &limits).unwrap_err();
+
+        assert!(err.to_string().contains("more than 2 entries"), "{}", err);
+        assert!(frame_names(&frames_dir).is_empty());
+    }
+
+    #[test]
+    fn invalid_image_entries_are_rejected() {
+        let (_dir, archive, frames_dir) = zip_archive(&[("good.png", png()), ("bad.png", b"not a png".to_vec())]);
+
+        let extracted = extract_archive(&archive, ArchiveKind::Zip, &frames_dir, &limits()).unwrap();
+
+        assert_eq!(extracted.filenames, ["good.png"]);
+        assert_eq!(extracted.rejected.len(), 1);
+        assert_eq!(extracted.rejected[0].filename, "bad.png");
+        assert_eq!(frame_names(&frames_dir), ["good.png"]);
+    }
+}
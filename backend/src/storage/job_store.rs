@@ -0,0 +1,661 @@
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use anyhow::{Result, Context};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use crate::models::{CreateTimelapseRequest, JobStatusType, ProcessingProgress};
+
+/// Error recorded for jobs that were still rendering when the backend stopped
+const INTERRUPTED_ERROR: &str = "Render was interrupted by a backend restart";
+
+/// Status updates buffered per subscriber before it starts missing them
+const EVENT_CAPACITY: usize = 1024;
+
+/// A job's new status, broadcast on every status or progress change
+#[derive(Debug, Clone)]
+pub struct JobEvent {
+    pub job_id: String,
+    pub status: JobStatusType,
+}
+
+/// A single status change of a job, as written to the job log
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StatusTransition {
+    pub status: String,
+    pub error: Option<String>,
+    pub at: DateTime<Utc>,
+}
+
+/// Everything the backend knows about a job
+#[derive(Debug, Clone)]
+pub struct JobRecord {
+    pub job_id: String,
+    pub file_count: usize,
+    pub settings: Option<CreateTimelapseRequest>,
+    pub status: JobStatusType,
+    pub transitions: Vec<StatusTransition>,
+    pub created_at: DateTime<Utc>,
+    /// Pinned jobs are never removed by the reaper
+    pub pinned: bool,
+    /// Last time frames were added to the job
+    pub touched_at: Option<DateTime<Utc>>,
+}
+
+impl JobRecord {
+    /// Time of the last status change or frame upload, used to decide when a
+    /// job expires
+    pub fn updated_at(&self) -> DateTime<Utc> {
+        let changed = self.transitions.last().map(|t| t.at).unwrap_or(self.created_at);
+        self.touched_at.map_or(changed, |touched| touched.max(changed))
+    }
+}
+
+/// One line of the append-only job log
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "camelCase")]
+enum LogEntry {
+    #[serde(rename_all = "camelCase")]
+    Created {
+        job_id: String,
+        file_count: usize,
+        at: DateTime<Utc>,
+    },
+    #[serde(rename_all = "camelCase")]
+    Settings {
+        job_id: String,
+        /// `None` when a rejected render's settings were taken back
+        settings: Option<Box<CreateTimelapseRequest>>,
+        at: DateTime<Utc>,
+    },
+    #[serde(rename_all = "camelCase")]
+    Status {
+        job_id: String,
+        #[serde(flatten)]
+        transition: StatusTransition,
+    },
+    #[serde(rename_all = "camelCase")]
+    Pinned {
+        job_id: String,
+        pinned: bool,
+        at: DateTime<Utc>,
+    },
+    #[serde(rename_all = "camelCase")]
+    Touched {
+        job_id: String,
+        at: DateTime<Utc>,
+    },
+    #[serde(rename_all = "camelCase")]
+    Removed {
+        job_id: String,
+        at: DateTime<Utc>,
+    },
+}
+
+struct Inner {
+    jobs: HashMap<String, JobRecord>,
+    log: File,
+    /// Requests currently writing frames into each job; not persisted, since
+    /// they all end with the process
+    uploading: HashMap<String, usize>,
+}
+
+/// Why a job can't receive frames right now
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UploadRefused {
+    UnknownJob,
+    /// Queued or rendering; its frame list is read when the render starts
+    Busy,
+}
+
+/// Marks a job as receiving frames until dropped, see [`JobStore::begin_upload`]
+pub struct UploadGuard {
+    inner: Arc<Mutex<Inner>>,
+    job_id: String,
+}
+
+impl Drop for UploadGuard {
+    fn drop(&mut self) {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(count) = inner.uploading.get_mut(&self.job_id) {
+            *count -= 1;
+            if *count == 0 {
+                inner.uploading.remove(&self.job_id);
+            }
+        }
+    }
+}
+
+/// Durable job store backed by an append-only JSON-lines log under the storage root.
+///
+/// Status transitions and render settings are persisted; per-frame progress
+/// updates only live in memory since they are rewritten on every FFmpeg line.
+/// Every change is also broadcast to subscribers, see [`JobStore::subscribe`].
+#[derive(Clone)]
+pub struct JobStore {
+    inner: Arc<Mutex<Inner>>,
+    events: broadcast::Sender<JobEvent>,
+}
+
+impl JobStore {
+    /// Open the store, replaying the log and failing any job that was mid-render
+    pub fn open(path: &Path) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .context("Failed to create job store directory")?;
+        }
+
+        let mut jobs = replay_log(path)?;
+
+        // Anything still processing was killed along with the previous process
+        for record in jobs.values_mut() {
+            if matches!(record.status, JobStatusType::Processing(_)) {
+                let status = JobStatusType::Failed(INTERRUPTED_ERROR.to_string());
+                record.transitions.push(transition_for(&status));
+                record.status = status;
+            }
+        }
+
+        // Rewrite the log from the replayed state, dropping any torn trailing line
+        compact_log(path, &jobs)?;
+
+        let log = OpenOptions::new()
+            .append(true)
+            .open(path)
+            .context("Failed to open job log")?;
+
+        let (events, _) = broadcast::channel(EVENT_CAPACITY);
+
+        Ok(Self {
+            inner: Arc::new(Mutex::new(Inner { jobs, log, uploading: HashMap::new() })),
+            events,
+        })
+    }
+
+    /// Register a freshly uploaded job in the pending state
+    pub fn create_job(&self, job_id: &str, file_count: usize) {
+        let now = Utc::now();
+        let status = JobStatusType::Pending;
+        let record = JobRecord {
+            job_id: job_id.to_string(),
+            file_count,
+            settings: None,
+            transitions: vec![transition_for(&status)],
+            status,
+            created_at: now,
+            pinned: false,
+            touched_at: None,
+        };
+
+        let mut inner = self.inner.lock().unwrap();
+        inner.append(&LogEntry::Created {
+            job_id: job_id.to_string(),
+            file_count,
+            at: now,
+        });
+        inner.append(&LogEntry::Status {
+            job_id: job_id.to_string(),
+            transition: record.transitions[0].clone(),
+        });
+        inner.jobs.insert(job_id.to_string(), record);
+    }
+
+    /// Receive status and progress changes of all jobs as they happen
+    pub fn subscribe(&self) -> broadcast::Receiver<JobEvent> {
+        self.events.subscribe()
+    }
+
+    /// Tell subscribers about a job's new status; sent under the store lock so
+    /// events arrive in the order the changes were made
+    fn publish(&self, job_id: &str, status: &JobStatusType) {
+        // Sending only fails when nobody is listening
+        let _ = self.events.send(JobEvent {
+            job_id: job_id.to_string(),
+            status: status.clone(),
+        });
+    }
+
+    /// Re-broadcast a job's current status, for changes the store doesn't track
+    /// itself, like the job entering the render queue
+    pub fn announce(&self, job_id: &str) {
+        let inner = self.inner.lock().unwrap();
+        if let Some(record) = inner.jobs.get(job_id) {
+            self.publish(job_id, &record.status);
+        }
+    }
+
+    pub fn get(&self, job_id: &str) -> Option<JobRecord> {
+        self.inner.lock().unwrap().jobs.get(job_id).cloned()
+    }
+
+    /// Jobs that were waiting for a worker, oldest first, with their settings
+    pub fn queued_jobs(&self) -> Vec<(String, CreateTimelapseRequest)> {
+        let inner = self.inner.lock().unwrap();
+        let mut queued: Vec<&JobRecord> = inner.jobs.values()
+            .filter(|r| matches!(r.status, JobStatusType::Queued))
+            .collect();
+        queued.sort_by_key(|r| r.transitions.last().map(|t| t.at));
+
+        queued.into_iter()
+            .filter_map(|r| Some((r.job_id.clone(), r.settings.clone()?)))
+            .collect()
+    }
+
+    /// Render settings a job was last started with
+    pub fn settings(&self, job_id: &str) -> Option<CreateTimelapseRequest> {
+        self.inner.lock().unwrap().jobs.get(job_id).and_then(|r| r.settings.clone())
+    }
+
+    pub fn status(&self, job_id: &str) -> Option<JobStatusType> {
+        self.inner.lock().unwrap().jobs.get(job_id).map(|r| r.status.clone())
+    }
+
+    /// Mark a job that isn't queued or rendering as receiving frames, until the
+    /// returned guard is dropped. Renders can't be queued in the meantime, so
+    /// the frames never change underneath one.
+    pub fn begin_upload(&self, job_id: &str) -> Result<UploadGuard, UploadRefused> {
+        let mut inner = self.inner.lock().unwrap();
+        let Some(record) = inner.jobs.get(job_id) else {
+            return Err(UploadRefused::UnknownJob);
+        };
+        if matches!(record.status, JobStatusType::Queued | JobStatusType::Processing(_)) {
+            return Err(UploadRefused::Busy);
+        }
+
+        *inner.uploading.entry(job_id.to_string()).or_default() += 1;
+        Ok(UploadGuard {
+            inner: self.inner.clone(),
+            job_id: job_id.to_string(),
+        })
+    }
+
+    /// Queue a render of a job that isn't already queued, rendering or
+    /// receiving frames, recording its settings. Checked and changed under one
+    /// lock, so two requests for the same job can't both queue it. Returns the
+    /// job's previous status and settings, for [`JobStore::restore`] if the
+    /// render can't be queued after all, or `None` if the job is unknown or busy.
+    pub fn try_mark_queued(
+        &self,
+        job_id: &str,
+        settings: &CreateTimelapseRequest,
+    ) -> Option<(JobStatusType, Option<CreateTimelapseRequest>)> {
+        let mut inner = self.inner.lock().unwrap();
+        let record = inner.jobs.get(job_id)?;
+        if matches!(record.status, JobStatusType::Queued | JobStatusType::Processing(_))
+            || inner.uploading.contains_key(job_id)
+        {
+            return None;
+        }
+
+        let previous = (record.status.clone(), record.settings.clone());
+        self.apply_settings(&mut inner, job_id, Some(settings.clone()));
+        self.apply_status(&mut inner, job_id, JobStatusType::Queued);
+        Some(previous)
+    }
+
+    /// Put back the status and settings [`JobStore::try_mark_queued`] replaced
+    pub fn restore(&self, job_id: &str, status: JobStatusType, settings: Option<CreateTimelapseRequest>) {
+        let mut inner = self.inner.lock().unwrap();
+        self.apply_settings(&mut inner, job_id, settings);
+        self.apply_status(&mut inner, job_id, status);
+    }
+
+    fn apply_settings(&self, inner: &mut Inner, job_id: &str, settings: Option<CreateTimelapseRequest>) {
+        let Some(record) = inner.jobs.get_mut(job_id) else {
+            return;
+        };
+
+        record.settings = settings.clone();
+        inner.append(&LogEntry::Settings {
+            job_id: job_id.to_string(),
+            settings: settings.map(Box::new),
+            at: Utc::now(),
+        });
+    }
+
+    /// Move a job to a new status, persisting it if the state actually changed
+    pub fn set_status(&self, job_id: &str, status: JobStatusType) {
+        let mut inner = self.inner.lock().unwrap();
+        self.apply_status(&mut inner, job_id, status);
+    }
+
+    fn apply_status(&self, inner: &mut Inner, job_id: &str, status: JobStatusType) {
+        let Some(record) = inner.jobs.get_mut(job_id) else {
+            return;
+        };
+
+        let changed = record.status.as_str() != status.as_str()
+            || error_of(&record.status) != error_of(&status);
+        record.status = status;
+        self.publish(job_id, &record.status);
+
+        if changed {
+            let transition = transition_for(&record.status);
+            record.transitions.push(transition.clone());
+            inner.append(&LogEntry::Status {
+                job_id: job_id.to_string(),
+                transition,
+            });
+        }
+    }
+
+    /// Exempt a job from (or return it to) automatic expiry
+    pub fn set_pinned(&self, job_id: &str, pinned: bool) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        let Some(record) = inner.jobs.get_mut(job_id) else {
+            return false;
+        };
+
+        record.pinned = pinned;
+        inner.append(&LogEntry::Pinned {
+            job_id: job_id.to_string(),
+            pinned,
+            at: Utc::now(),
+        });
+        true
+    }
+
+    /// Record that frames are being added to a job, so it doesn't expire while
+    /// the upload is running or soon after
+    pub fn touch(&self, job_id: &str) {
+        let mut inner = self.inner.lock().unwrap();
+        let Some(record) = inner.jobs.get_mut(job_id) else {
+            return;
+        };
+
+        let now = Utc::now();
+        record.touched_at = Some(now);
+        inner.append(&LogEntry::Touched {
+            job_id: job_id.to_string(),
+            at: now,
+        });
+    }
+
+    /// Forget a job entirely once its files are gone
+    /// Remove every job `is_expired` accepts, except queued, rendering and
+    /// pinned ones, and return their IDs. Checked and removed under one lock,
+    /// so a job that is re-rendered, pinned or touched meanwhile is kept.
+    pub fn remove_expired(&self, is_expired: impl Fn(&JobRecord) -> bool) -> Vec<String> {
+        let mut inner = self.inner.lock().unwrap();
+        let expired: Vec<String> = inner
+            .jobs
+            .values()
+            .filter(|record| {
+                !record.pinned
+                    && !matches!(record.status, JobStatusType::Queued | JobStatusType::Processing(_))
+                    && is_expired(record)
+            })
+            .map(|record| record.job_id.clone())
+            .collect();
+
+        let at = Utc::now();
+        for job_id in &expired {
+            inner.jobs.remove(job_id);
+            inner.append(&LogEntry::Removed { job_id: job_id.clone(), at });
+        }
+        expired
+    }
+
+    /// Run `f` while holding the store lock if the store has no record of
+    /// `job_id`, so none can be created until it returns. `None` if the job is
+    /// known. `f` must not call back into the store.
+    pub fn with_unknown_job<T>(&self, job_id: &str, f: impl FnOnce() -> T) -> Option<T> {
+        let inner = self.inner.lock().unwrap();
+        if inner.jobs.contains_key(job_id) {
+            return None;
+        }
+        Some(f())
+    }
+
+    /// Update in-memory render progress without touching the log.
+    ///
+    /// Ignored unless the job is processing, so a late FFmpeg line can't
+    /// resurrect a job that was cancelled in the meantime.
+    pub fn set_progress(&self, job_id: &str, progress: ProcessingProgress) {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(record) = inner.jobs.get_mut(job_id) {
+            if matches!(record.status, JobStatusType::Processing(_)) {
+                record.status = JobStatusType::Processing(Some(progress));
+                self.publish(job_id, &record.status);
+            }
+        }
+    }
+}
+
+impl Inner {
+    fn append(&mut self, entry: &LogEntry) {
+        let result = serde_json::to_string(entry)
+            .map_err(anyhow::Error::from)
+            .and_then(|line| {
+                writeln!(self.log, "{}", line)?;
+                self.log.sync_data()?;
+                Ok(())
+            });
+
+        if let Err(e) = result {
+            eprintln!("Failed to persist job log entry: {}", e);
+        }
+    }
+}
+
+fn error_of(status: &JobStatusType) -> Option<&str> {
+    match status {
+        JobStatusType::Failed(err) => Some(err),
+        _ => None,
+    }
+}
+
+fn transition_for(status: &JobStatusType) -> StatusTransition {
+    StatusTransition {
+        status: status.as_str().to_string(),
+        error: error_of(status).map(|e| e.to_string()),
+        at: Utc::now(),
+    }
+}
+
+fn status_from_transition(transition: &StatusTransition) -> JobStatusType {
+    match transition.status.as_str() {
+        "queued" => JobStatusType::Queued,
+        "processing" => JobStatusType::Processing(None),
+        "completed" => JobStatusType::Completed,
+        "cancelled" => JobStatusType::Cancelled,
+        "failed" => JobStatusType::Failed(transition.error.clone().unwrap_or_default()),
+        _ => JobStatusType::Pending,
+    }
+}
+
+fn replay_log(path: &Path) -> Result<HashMap<String, JobRecord>> {
+    let mut jobs: HashMap<String, JobRecord> = HashMap::new();
+
+    if !path.exists() {
+        return Ok(jobs);
+    }
+
+    let file = File::open(path).context("Failed to open job log")?;
+    for line in BufReader::new(file).lines() {
+        let line = line.context("Failed to read job log")?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        // A crash mid-write can leave a partial last line; skip anything unparsable
+        let Ok(entry) = serde_json::from_str::<LogEntry>(&line) else {
+            eprintln!("Skipping unreadable job log entry");
+            continue;
+        };
+
+        match entry {
+            LogEntry::Created { job_id, file_count, at } => {
+                jobs.insert(job_id.clone(), JobRecord {
+                    job_id,
+                    file_count,
+                    settings: None,
+                    status: JobStatusType::Pending,
+                    transitions: Vec::new(),
+                    created_at: at,
+                    pinned: false,
+                    touched_at: None,
+                });
+            }
+            LogEntry::Settings { job_id, settings, .. } => {
+                if let Some(record) = jobs.get_mut(&job_id) {
+                    record.settings = settings.map(|s| *s);
+                }
+            }
+            LogEntry::Status { job_id, transition } => {
+                if let Some(record) = jobs.get_mut(&job_id) {
+                    record.status = status_from_transition(&transition);
+                    record.transitions.push(transition);
+                }
+            }
+            LogEntry::Pinned { job_id, pinned, .. } => {
+                if let Some(record) = jobs.get_mut(&job_id) {
+                    record.pinned = pinned;
+                }
+            }
+            LogEntry::Touched { job_id, at } => {
+                if let Some(record) = jobs.get_mut(&job_id) {
+                    record.touched_at = Some(at);
+                }
+            }
+            LogEntry::Removed { job_id, .. } => {
+                jobs.remove(&job_id);
+            }
+        }
+    }
+
+    Ok(jobs)
+}
+
+fn compact_log(path: &Path, jobs: &HashMap<String, JobRecord>) -> Result<()> {
+    let tmp_path: PathBuf = path.with_extension("jsonl.tmp");
+    let mut out = File::create(&tmp_path).context("Failed to create job log")?;
+
+    let mut records: Vec<&JobRecord> = jobs.values().collect();
+    records.sort_by_key(|r| r.created_at);
+
+    for record in records {
+        let mut entries = vec![LogEntry::Created {
+            job_id: record.job_id.clone(),
+            file_count: record.file_count,
+            at: record.created_at,
+        }];
+        if let Some(settings) = &record.settings {
+            entries.push(LogEntry::Settings {
+                job_id: record.job_id.clone(),
+                settings: Some(Box::new(settings.clone())),
+                at: record.created_at,
+            });
+        }
+        for transition in &record.transitions {
+            entries.push(LogEntry::Status {
+                job_id: record.job_id.clone(),
+                transition: transition.clone(),
+            });
+        }
+        if let Some(at) = record.touched_at {
+            entries.push(LogEntry::Touched {
+                job_id: record.job_id.clone(),
+                at,
+            });
+        }
+        if record.pinned {
+            entries.push(LogEntry::Pinned {
+                job_id: record.job_id.clone(),
+                pinned: true,
+                at: record.updated_at(),
+            });
+        }
+
+        for entry in entries {
+            writeln!(out, "{}", serde_json::to_string(&entry)?)
+                .context("Failed to write job log")?;
+        }
+    }
+
+    out.sync_all().context("Failed to sync job log")?;
+    fs::rename(&tmp_path, path).context("Failed to replace job log")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn remove_expired_keeps_busy_and_pinned_jobs() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = JobStore::open(&dir.path().join("jobs.jsonl")).unwrap();
+        for (job_id, status) in [
+            ("pending", JobStatusType::Pending),
+            ("queued", JobStatusType::Queued),
+            ("processing", JobStatusType::Processing(None)),
+            ("completed", JobStatusType::Completed),
+            ("pinned", JobStatusType::Completed),
+        ] {
+            store.create_job(job_id, 1);
+            store.set_status(job_id, status);
+        }
+        store.set_pinned("pinned", true);
+
+        let mut removed = store.remove_expired(|_| true);
+        removed.sort();
+
+        assert_eq!(removed, ["completed", "pending"]);
+        assert!(store.get("completed").is_none());
+        assert!(store.get("queued").is_some());
+        assert!(store.get("processing").is_some());
+        assert!(store.get("pinned").is_some());
+    }
+
+    #[test]
+    fn removed_jobs_stay_removed_after_reopening() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("jobs.jsonl");
+        let store = JobStore::open(&path).unwrap();
+        store.create_job("old", 1);
+        store.create_job("new", 1);
+
+        store.remove_expired(|record| record.job_id == "old");
+        drop(store);
+
+        let store = JobStore::open(&path).unwrap();
+        assert!(store.get("old").is_none());
+        assert!(store.get("new").is_some());
+    }
+
+    #[test]
+    fn uploads_and_renders_exclude_each_other() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = JobStore::open(&dir.path().join("jobs.jsonl")).unwrap();
+        let settings: CreateTimelapseRequest =
+            serde_json::from_value(serde_json::json!({ "jobId": "job", "rotation": 0, "fps": 10.0 })).unwrap();
+        store.create_job("job", 1);
+
+        assert_eq!(store.begin_upload("missing").err(), Some(UploadRefused::UnknownJob));
+
+        let first = store.begin_upload("job").unwrap();
+        let second = store.begin_upload("job").unwrap();
+        assert!(store.try_mark_queued("job", &settings).is_none());
+        drop(first);
+        assert!(store.try_mark_queued("job", &settings).is_none());
+        drop(second);
+
+        assert!(store.try_mark_queued("job", &settings).is_some());
+        assert_eq!(store.begin_upload("job").err(), Some(UploadRefused::Busy));
+    }
+
+    #[test]
+    fn with_unknown_job_only_runs_for_unknown_jobs() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = JobStore::open(&dir.path().join("jobs.jsonl")).unwrap();
+        store.create_job("known", 1);
+
+        assert_eq!(store.with_unknown_job("known", || 1), None);
+        assert_eq!(store.with_unknown_job("unknown", || 1), Some(1));
+    }
+}
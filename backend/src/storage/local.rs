@@ -1,6 +1,7 @@
 use std::fs;
 use std::path::{Path, PathBuf};
 use anyhow::{Result, Context};
+use uuid::Uuid;
 
 const TEMP_BASE_DIR: &str = "/tmp/timelapse";
 
@@ -16,17 +17,69 @@ pub fn ensure_job_directory(job_id: &str) -> Result<PathBuf> {
     Ok(job_dir)
 }
 
+/// Whether `job_id` has the form of the job IDs the backend hands out. Job IDs
+/// become directory names, so anything else could point outside the storage root.
+pub fn is_valid_job_id(job_id: &str) -> bool {
+    Uuid::parse_str(job_id).is_ok_and(|id| id.hyphenated().to_string() == job_id)
+}
+
+pub fn get_job_directory(job_id: &str) -> PathBuf {
+    Path::new(TEMP_BASE_DIR).join(job_id)
+}
+
+/// Whether a filename has one of the image extensions we accept as frames
+pub fn is_supported_image(filename: &str) -> bool {
+    let ext = filename
+        .rsplit_once('.')
+        .map(|(_, ext)| ext.to_lowercase())
+        .unwrap_or_default();
+    matches!(ext.as_str(), "png" | "jpg" | "jpeg" | "webp")
+}
+
 pub fn get_frames_directory(job_id: &str) -> PathBuf {
     Path::new(TEMP_BASE_DIR).join(job_id).join("frames")
 }
 
-pub fn get_output_path(job_id: &str) -> PathBuf {
-    Path::new(TEMP_BASE_DIR).join(job_id).join("output.mp4")
+pub fn get_job_store_path() -> PathBuf {
+    Path::new(TEMP_BASE_DIR).join("jobs.jsonl")
+}
+
+/// Path of a job's rendered video with the given file extension
+pub fn get_output_path(job_id: &str, extension: &str) -> PathBuf {
+    Path::new(TEMP_BASE_DIR).join(job_id).join(format!("output.{}", extension))
+}
+
+/// Path of a job's draft render
+pub fn get_draft_path(job_id: &str) -> PathBuf {
+    Path::new(TEMP_BASE_DIR).join(job_id).join("draft.mp4")
+}
+
+/// Directory of a job's cached preview thumbnails
+pub fn get_thumbnails_directory(job_id: &str) -> PathBuf {
+    Path::new(TEMP_BASE_DIR).join(job_id).join("thumbnails")
+}
+
+/// Directory of a job's cached contact sheets
+pub fn get_contact_sheets_directory(job_id: &str) -> PathBuf {
+    Path::new(TEMP_BASE_DIR).join(job_id).join("contact_sheets")
+}
+
+/// Directory holding the outputs of a render with several renditions
+pub fn get_renditions_directory(job_id: &str) -> PathBuf {
+    Path::new(TEMP_BASE_DIR).join(job_id).join("renditions")
+}
+
+/// Path of one rendition of a job's render with the given file extension
+pub fn get_rendition_path(job_id: &str, rendition_id: &str, extension: &str) -> PathBuf {
+    get_renditions_directory(job_id).join(format!("{}.{}", rendition_id, extension))
 }
 
-#[allow(dead_code)]
 pub fn cleanup_job(job_id: &str) -> Result<()> {
-    let job_dir = Path::new(TEMP_BASE_DIR).join(job_id);
+    if !is_valid_job_id(job_id) {
+        anyhow::bail!("Refusing to remove directory of invalid job ID {:?}", job_id);
+    }
+
+    let job_dir = get_job_directory(job_id);
     if job_dir.exists() {
         fs::remove_dir_all(&job_dir)
             .context("Failed to cleanup job directory")?;
@@ -34,6 +87,25 @@ pub fn cleanup_job(job_id: &str) -> Result<()> {
     Ok(())
 }
 
+/// Names of all job directories under the storage root
+pub fn list_job_directories() -> Result<Vec<String>> {
+    let mut job_ids = Vec::new();
+    let base = Path::new(TEMP_BASE_DIR);
+
+    if !base.exists() {
+        return Ok(job_ids);
+    }
+
+    for entry in fs::read_dir(base).context("Failed to read storage directory")? {
+        let entry = entry.context("Failed to read directory entry")?;
+        if entry.path().is_dir() {
+            job_ids.push(entry.file_name().to_string_lossy().to_string());
+        }
+    }
+
+    Ok(job_ids)
+}
+
 pub fn list_image_files(job_id: &str) -> Result<Vec<String>> {
     let frames_dir = get_frames_directory(job_id);
     let mut files = Vec::new();
@@ -50,12 +122,10 @@ pub fn list_image_files(job_id: &str) -> Result<Vec<String>> {
         let path = entry.path();
         
         if path.is_file() {
-            if let Some(ext) = path.extension() {
-                let ext_lower = ext.to_string_lossy().to_lowercase();
-                if matches!(ext_lower.as_str(), "png" | "jpg" | "jpeg" | "webp") {
-                    if let Some(filename) = path.file_name() {
-                        files.push(filename.to_string_lossy().to_string());
-                    }
+            if let Some(filename) = path.file_name() {
+                let filename = filename.to_string_lossy().to_string();
+                if is_supported_image(&filename) {
+                    files.push(filename);
                 }
             }
         }
@@ -68,3 +138,32 @@ pub fn list_image_files(job_id: &str) -> Result<Vec<String>> {
     
     Ok(files)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_hyphenated_uuids_are_job_ids() {
+        assert!(is_valid_job_id(&Uuid::new_v4().to_string()));
+
+        for job_id in [
+            "",
+            "..",
+            "../../home/x",
+            "frames",
+            "67e55044-10b1-426f-9247-bb680e5fe0c8/..",
+            "67e5504410b1426f9247bb680e5fe0c8",
+            "{67e55044-10b1-426f-9247-bb680e5fe0c8}",
+            "urn:uuid:67e55044-10b1-426f-9247-bb680e5fe0c8",
+            "67E55044-10B1-426F-9247-BB680E5FE0C8",
+        ] {
+            assert!(!is_valid_job_id(job_id), "{}", job_id);
+        }
+    }
+
+    #[test]
+    fn cleanup_refuses_invalid_job_ids() {
+        assert!(cleanup_job("../../tmp").is_err());
+    }
+}
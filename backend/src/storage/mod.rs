@@ -1 +1,5 @@
 pub mod local;
+pub mod archive;
+pub mod job_store;
+pub mod reaper;
+pub mod uploads;
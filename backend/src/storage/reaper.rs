@@ -0,0 +1,107 @@
+use std::fs;
+use std::time::{Duration, SystemTime};
+use actix_web::web;
+use chrono::Utc;
+use crate::config::Config;
+use crate::models::JobStatusType;
+use crate::storage::job_store::JobStore;
+use crate::storage::local::{cleanup_job, get_frames_directory, get_job_directory, is_valid_job_id, list_job_directories};
+
+/// How long each kind of job is kept after its last status change
+#[derive(Debug, Clone)]
+pub struct Retention {
+    /// Uploaded frames that were never rendered, or whose render was cancelled
+    pub uploads: Duration,
+    pub completed: Duration,
+    pub failed: Duration,
+}
+
+impl Retention {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            uploads: hours(config.upload_ttl_hours),
+            completed: hours(config.completed_job_ttl_hours),
+            failed: hours(config.failed_job_ttl_hours),
+        }
+    }
+
+    /// Retention for a job in the given state, or `None` if it must be kept
+    fn ttl_for(&self, status: &JobStatusType) -> Option<Duration> {
+        match status {
+            JobStatusType::Pending | JobStatusType::Cancelled => Some(self.uploads),
+            JobStatusType::Completed => Some(self.completed),
+            JobStatusType::Failed(_) => Some(self.failed),
+            JobStatusType::Queued | JobStatusType::Processing(_) => None,
+        }
+    }
+}
+
+fn hours(h: u64) -> Duration {
+    Duration::from_secs(h * 60 * 60)
+}
+
+/// Spawn the background task that periodically removes expired jobs
+pub fn start(job_store: JobStore, retention: Retention, interval: Duration) {
+    actix_web::rt::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+
+            // Directory removal can take a while for big frame sets, keep it off the event loop
+            let job_store = job_store.clone();
+            let retention = retention.clone();
+            let removed = web::block(move || reap_expired_jobs(&job_store, &retention))
+                .await
+                .unwrap_or(0);
+            if removed > 0 {
+                println!("Reaper removed {} expired job(s)", removed);
+            }
+        }
+    });
+}
+
+/// Remove every expired, unpinned job and return how many were deleted
+fn reap_expired_jobs(job_store: &JobStore, retention: &Retention) -> usize {
+    let now = Utc::now();
+    let mut removed = 0;
+
+    // Jobs leave the store before their directories go, so no request can
+    // start using a directory that is about to be deleted
+    let expired = job_store.remove_expired(|record| {
+        retention.ttl_for(&record.status).is_some_and(|ttl| {
+            (now - record.updated_at()).to_std().unwrap_or_default() >= ttl
+        })
+    });
+    for job_id in expired {
+        // A directory left behind is picked up again as an orphan below
+        match cleanup_job(&job_id) {
+            Ok(()) => removed += 1,
+            Err(e) => eprintln!("Failed to remove expired job {}: {:#}", job_id, e),
+        }
+    }
+
+    // Directories without a store entry (e.g. uploads from before the job store existed)
+    // fall under the upload retention, measured from their last modification
+    let job_ids = list_job_directories().unwrap_or_default();
+    for job_id in job_ids.into_iter().filter(|job_id| is_valid_job_id(job_id)) {
+        // Checked and removed under the store lock, so a job can't be created
+        // for the directory while it's deleted. Such directories are rare
+        let reaped = job_store.with_unknown_job(&job_id, || {
+            let modified = [get_job_directory(&job_id), get_frames_directory(&job_id)]
+                .iter()
+                .filter_map(|p| fs::metadata(p).and_then(|m| m.modified()).ok())
+                .max();
+            let Some(modified) = modified else {
+                return false;
+            };
+
+            let age = SystemTime::now().duration_since(modified).unwrap_or_default();
+            age >= retention.uploads && cleanup_job(&job_id).is_ok()
+        });
+        if reaped == Some(true) {
+            removed += 1;
+        }
+    }
+
+    removed
+}
@@ -0,0 +1,173 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use anyhow::{Result, Context};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use crate::storage::local::{get_frames_directory, get_job_directory};
+
+/// Metadata of a resumable upload, stored next to its partial data
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UploadSession {
+    pub upload_id: String,
+    pub job_id: String,
+    /// Sanitized name the frame will get in the frames directory
+    pub filename: String,
+    pub size: u64,
+    pub complete: bool,
+}
+
+impl UploadSession {
+    /// Bytes received so far; the partial file on disk is the source of truth
+    pub fn offset(&self) -> u64 {
+        if self.complete {
+            return self.size;
+        }
+        fs::metadata(self.data_path())
+            .map(|m| m.len())
+            .unwrap_or(0)
+    }
+
+    pub fn data_path(&self) -> PathBuf {
+        get_uploads_directory(&self.job_id).join(format!("{}.part", self.upload_id))
+    }
+
+    /// Where the finished upload ends up
+    pub fn frame_path(&self) -> PathBuf {
+        get_frames_directory(&self.job_id).join(&self.filename)
+    }
+
+    fn meta_path(&self) -> PathBuf {
+        get_uploads_directory(&self.job_id).join(format!("{}.json", self.upload_id))
+    }
+
+    fn save(&self) -> Result<()> {
+        let json = serde_json::to_vec(self)?;
+        fs::write(self.meta_path(), json).context("Failed to write upload metadata")
+    }
+}
+
+/// Uploads a request is currently writing to. Each upload takes one chunk at a
+/// time; a second request at the same offset would append the same bytes twice.
+#[derive(Clone, Default)]
+pub struct ActiveUploads {
+    upload_ids: Arc<Mutex<HashSet<String>>>,
+}
+
+/// Holds an upload for one request until dropped, see [`ActiveUploads::try_lock`]
+pub struct ActiveUpload {
+    upload_ids: Arc<Mutex<HashSet<String>>>,
+    upload_id: String,
+}
+
+impl ActiveUploads {
+    /// Claim an upload for the calling request, or `None` if another request has it
+    pub fn try_lock(&self, upload_id: &str) -> Option<ActiveUpload> {
+        if !self.upload_ids.lock().unwrap().insert(upload_id.to_string()) {
+            return None;
+        }
+        Some(ActiveUpload {
+            upload_ids: self.upload_ids.clone(),
+            upload_id: upload_id.to_string(),
+        })
+    }
+}
+
+impl Drop for ActiveUpload {
+    fn drop(&mut self) {
+        self.upload_ids.lock().unwrap().remove(&self.upload_id);
+    }
+}
+
+pub fn get_uploads_directory(job_id: &str) -> PathBuf {
+    get_job_directory(job_id).join("uploads")
+}
+
+/// Start a new resumable upload of `size` bytes into a job's frames directory
+pub fn create_upload(job_id: &str, filename: &str, size: u64) -> Result<UploadSession> {
+    fs::create_dir_all(get_uploads_directory(job_id))
+        .context("Failed to create uploads directory")?;
+
+    let session = UploadSession {
+        upload_id: Uuid::new_v4().to_string(),
+        job_id: job_id.to_string(),
+        filename: sanitize_filename::sanitize(filename),
+        size,
+        complete: false,
+    };
+
+    fs::File::create(session.data_path()).context("Failed to create upload file")?;
+    session.save()?;
+    Ok(session)
+}
+
+/// Look up an upload, returning `None` if the job or upload doesn't exist
+pub fn load_upload(job_id: &str, upload_id: &str) -> Result<Option<UploadSession>> {
+    // Only accept real upload IDs so the path can't escape the uploads directory
+    if Uuid::parse_str(upload_id).is_err() {
+        return Ok(None);
+    }
+
+    let meta_path = get_uploads_directory(job_id).join(format!("{}.json", upload_id));
+    if !meta_path.exists() {
+        return Ok(None);
+    }
+
+    let json = fs::read(&meta_path).context("Failed to read upload metadata")?;
+    let session: UploadSession = serde_json::from_slice(&json)
+        .context("Failed to parse upload metadata")?;
+
+    // Metadata belongs to the directory it sits in, whatever the file says
+    if session.job_id != job_id || session.upload_id != upload_id {
+        return Ok(None);
+    }
+
+    Ok(Some(session))
+}
+
+/// Move a fully received upload into the frames directory. Returns `false`,
+/// leaving the upload where it is, if a frame of the same name has turned up
+/// since the upload was started.
+pub fn complete_upload(session: &mut UploadSession) -> Result<bool> {
+    // Unlike a rename, linking fails instead of replacing an existing frame
+    match fs::hard_link(session.data_path(), session.frame_path()) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => return Ok(false),
+        Err(e) => return Err(e).context("Failed to move upload into frames directory"),
+    }
+    fs::remove_file(session.data_path()).context("Failed to remove upload data")?;
+
+    session.complete = true;
+    session.save()?;
+    Ok(true)
+}
+
+/// Drop an upload and everything it wrote, e.g. when the finished file isn't a
+/// valid image. The frame is only removed once the upload has put it there.
+pub fn discard_upload(session: &UploadSession) {
+    let _ = fs::remove_file(session.data_path());
+    if session.complete {
+        let _ = fs::remove_file(session.frame_path());
+    }
+    let _ = fs::remove_file(session.meta_path());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn an_upload_is_held_by_one_request_at_a_time() {
+        let active = ActiveUploads::default();
+
+        let held = active.try_lock("upload").unwrap();
+        assert!(active.try_lock("upload").is_none());
+        assert!(active.try_lock("other").is_some());
+
+        drop(held);
+        assert!(active.try_lock("upload").is_some());
+    }
+}
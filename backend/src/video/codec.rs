@@ -0,0 +1,337 @@
+use std::ops::RangeInclusive;
+use serde::{Deserialize, Serialize};
+use crate::models::CreateTimelapseRequest;
+use crate::video::pacing::MAX_FPS;
+
+/// Video codecs a timelapse can be encoded with
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VideoCodec {
+    #[default]
+    H264,
+    H265,
+    Vp9,
+    Av1,
+    ProRes,
+    /// Animated GIF
+    Gif,
+    /// Animated WebP
+    WebP,
+}
+
+/// Container formats the encoded video can be written to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Container {
+    Mp4,
+    Webm,
+    Mov,
+    Gif,
+    Webp,
+}
+
+/// x264/x265 speed presets, fastest first
+const X26X_PRESETS: &[&str] = &[
+    "ultrafast", "superfast", "veryfast", "faster", "fast", "medium", "slow", "slower", "veryslow",
+];
+/// libvpx `-cpu-used` values for the `good` deadline, slowest first
+const VP9_PRESETS: &[&str] = &["0", "1", "2", "3", "4", "5"];
+/// SVT-AV1 presets, slowest first
+const AV1_PRESETS: &[&str] = &[
+    "0", "1", "2", "3", "4", "5", "6", "7", "8", "9", "10", "11", "12", "13",
+];
+/// ProRes profiles, in the order of their `prores_ks` profile numbers
+const PRORES_PROFILES: &[&str] = &["proxy", "lt", "standard", "hq", "4444", "4444xq"];
+/// `paletteuse` dithering algorithms, used as the GIF "preset"
+const GIF_DITHERS: &[&str] = &["none", "bayer", "floyd_steinberg", "sierra2_4a"];
+/// libwebp content presets
+const WEBP_PRESETS: &[&str] = &["default", "picture", "photo", "drawing", "icon", "text"];
+
+/// Browsers slow down GIF frames shorter than 2/100 s, so animated images are
+/// kept at or below 50 fps
+const ANIMATED_MAX_FPS: f64 = 50.0;
+
+/// Longest side of an animated image; bigger ones are too heavy to share
+pub const ANIMATED_MAX_SIDE: u32 = 1280;
+
+/// Longest side animated images are shrunk to when no size is requested
+pub const ANIMATED_DEFAULT_SIDE: u32 = 640;
+
+/// The GIF loop counter is 16 bits
+const MAX_LOOP_COUNT: u32 = 65535;
+
+impl VideoCodec {
+    pub fn name(&self) -> &'static str {
+        match self {
+            VideoCodec::H264 => "h264",
+            VideoCodec::H265 => "h265",
+            VideoCodec::Vp9 => "vp9",
+            VideoCodec::Av1 => "av1",
+            VideoCodec::ProRes => "prores",
+            VideoCodec::Gif => "gif",
+            VideoCodec::WebP => "webp",
+        }
+    }
+
+    /// Whether the output is an animated image rather than a video
+    pub fn is_animated_image(&self) -> bool {
+        matches!(self, VideoCodec::Gif | VideoCodec::WebP)
+    }
+
+    /// Highest frame rate the output may play at
+    pub fn max_fps(&self) -> f64 {
+        if self.is_animated_image() { ANIMATED_MAX_FPS } else { MAX_FPS }
+    }
+
+    fn encoder(&self) -> &'static str {
+        match self {
+            VideoCodec::H264 => "libx264",
+            VideoCodec::H265 => "libx265",
+            VideoCodec::Vp9 => "libvpx-vp9",
+            VideoCodec::Av1 => "libsvtav1",
+            VideoCodec::ProRes => "prores_ks",
+            VideoCodec::Gif => "gif",
+            VideoCodec::WebP => "libwebp_anim",
+        }
+    }
+
+    /// Container used when the request doesn't pick one
+    fn default_container(&self) -> Container {
+        match self {
+            VideoCodec::H264 | VideoCodec::H265 => Container::Mp4,
+            VideoCodec::Vp9 | VideoCodec::Av1 => Container::Webm,
+            VideoCodec::ProRes => Container::Mov,
+            VideoCodec::Gif => Container::Gif,
+            VideoCodec::WebP => Container::Webp,
+        }
+    }
+
+    fn fits_in(&self, container: Container) -> bool {
+        matches!(
+            (self, container),
+            (VideoCodec::H264 | VideoCodec::H265 | VideoCodec::Av1, Container::Mp4)
+                | (VideoCodec::Vp9 | VideoCodec::Av1, Container::Webm)
+                | (VideoCodec::H264 | VideoCodec::H265 | VideoCodec::ProRes, Container::Mov)
+                | (VideoCodec::Gif, Container::Gif)
+                | (VideoCodec::WebP, Container::Webp)
+        )
+    }
+
+    /// Valid CRF values and the default (libwebp's 0-100 quality scale for
+    /// WebP); ProRes has fixed-quality profiles and GIF a fixed palette instead
+    fn quality_range(&self) -> Option<(RangeInclusive<u32>, u32)> {
+        match self {
+            VideoCodec::H264 => Some((0..=51, 18)),
+            VideoCodec::H265 => Some((0..=51, 22)),
+            VideoCodec::Vp9 => Some((0..=63, 31)),
+            VideoCodec::Av1 => Some((0..=63, 30)),
+            VideoCodec::WebP => Some((0..=100, 75)),
+            VideoCodec::ProRes | VideoCodec::Gif => None,
+        }
+    }
+
+    /// Valid presets and the default
+    fn presets(&self) -> (&'static [&'static str], &'static str) {
+        match self {
+            VideoCodec::H264 => (X26X_PRESETS, "veryfast"),
+            VideoCodec::H265 => (X26X_PRESETS, "fast"),
+            VideoCodec::Vp9 => (VP9_PRESETS, "4"),
+            VideoCodec::Av1 => (AV1_PRESETS, "8"),
+            VideoCodec::ProRes => (PRORES_PROFILES, "hq"),
+            VideoCodec::Gif => (GIF_DITHERS, "sierra2_4a"),
+            VideoCodec::WebP => (WEBP_PRESETS, "photo"),
+        }
+    }
+}
+
+impl Container {
+    pub const ALL: [Container; 5] = [
+        Container::Mp4,
+        Container::Webm,
+        Container::Mov,
+        Container::Gif,
+        Container::Webp,
+    ];
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Container::Mp4 => "mp4",
+            Container::Webm => "webm",
+            Container::Mov => "mov",
+            Container::Gif => "gif",
+            Container::Webp => "webp",
+        }
+    }
+
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            Container::Mp4 => "video/mp4",
+            Container::Webm => "video/webm",
+            Container::Mov => "video/quicktime",
+            Container::Gif => "image/gif",
+            Container::Webp => "image/webp",
+        }
+    }
+}
+
+/// Validated encoder settings of a render request
+#[derive(Debug, Clone)]
+pub struct Encoding {
+    pub codec: VideoCodec,
+    pub container: Container,
+    quality: Option<u32>,
+    preset: &'static str,
+    /// Times an animated image plays; 0 loops forever
+    loop_count: u32,
+}
+
+impl Encoding {
+    /// Check the request's codec options against each other, filling in defaults
+    pub fn from_request(req: &CreateTimelapseRequest) -> Result<Self, String> {
+        let codec = req.codec;
+
+        let container = req.container.unwrap_or(codec.default_container());
+        if !codec.fits_in(container) {
+            return Err(format!(
+                "Codec {} can't be stored in a {} container",
+                codec.name(),
+                container.extension()
+            ));
+        }
+
+        let quality = match (codec.quality_range(), req.quality) {
+            (Some((range, _)), Some(q)) if !range.contains(&q) => {
+                return Err(format!(
+                    "Quality for {} must be between {} and {}",
+                    codec.name(),
+                    range.start(),
+                    range.end()
+                ));
+            }
+            (Some(_), Some(q)) => Some(q),
+            (Some((_, default)), None) => Some(default),
+            (None, Some(_)) if codec == VideoCodec::Gif => {
+                return Err("GIF has no quality setting; pick a dither with preset instead".to_string());
+            }
+            (None, Some(_)) => {
+                return Err("ProRes has no quality setting; pick a profile with preset instead".to_string());
+            }
+            (None, None) => None,
+        };
+
+        let (presets, default_preset) = codec.presets();
+        let preset = match &req.preset {
+            Some(p) => *presets.iter().find(|known| **known == p.as_str()).ok_or_else(|| {
+                format!("Preset for {} must be one of: {}", codec.name(), presets.join(", "))
+            })?,
+            None => default_preset,
+        };
+
+        if codec.is_animated_image() {
+            check_animation_limits(req)?;
+        } else if req.loop_count.is_some() {
+            return Err("loopCount only applies to GIF and WebP output".to_string());
+        }
+        let loop_count = req.loop_count.unwrap_or(0);
+
+        Ok(Self { codec, container, quality, preset, loop_count })
+    }
+
+    /// Filters that turn the rendered frames into the encoder's input, run
+    /// after the rest of the filter chain
+    pub fn filter(&self) -> Option<String> {
+        match self.codec {
+            // One palette for the whole animation, built from the pixels that
+            // change between frames, and only changed rectangles re-dithered
+            VideoCodec::Gif => Some(format!(
+                "split[frames][stats];[stats]palettegen=stats_mode=diff[palette];\
+                 [frames][palette]paletteuse=dither={}:diff_mode=rectangle",
+                self.preset
+            )),
+            _ => None,
+        }
+    }
+
+    /// FFmpeg output arguments for the video stream and container
+    pub fn ffmpeg_args(&self) -> Vec<String> {
+        let mut args: Vec<String> = vec!["-c:v".into(), self.codec.encoder().into()];
+
+        if let Some(quality) = self.quality {
+            let flag = if self.codec == VideoCodec::WebP { "-quality" } else { "-crf" };
+            args.extend([flag.into(), quality.to_string()]);
+        }
+
+        let pix_fmt = match self.codec {
+            VideoCodec::H264 | VideoCodec::Av1 => {
+                args.extend(["-preset".into(), self.preset.into()]);
+                "yuv420p"
+            }
+            VideoCodec::H265 => {
+                args.extend(["-preset".into(), self.preset.into()]);
+                // QuickTime and Safari only play HEVC tagged as hvc1
+                args.extend(["-tag:v".into(), "hvc1".into()]);
+                "yuv420p"
+            }
+            VideoCodec::Vp9 => {
+                // Constant quality mode needs the bitrate target disabled
+                args.extend(["-b:v".into(), "0".into()]);
+                args.extend(["-deadline".into(), "good".into(), "-cpu-used".into(), self.preset.into()]);
+                args.extend(["-row-mt".into(), "1".into()]);
+                "yuv420p"
+            }
+            VideoCodec::ProRes => {
+                let profile = PRORES_PROFILES.iter().position(|p| *p == self.preset).unwrap_or(3);
+                args.extend(["-profile:v".into(), profile.to_string()]);
+                args.extend(["-vendor".into(), "apl0".into()]);
+                if self.preset.starts_with("4444") { "yuv444p10le" } else { "yuv422p10le" }
+            }
+            VideoCodec::Gif => {
+                // The muxer counts repeats after the first play, with -1 for none
+                let repeats = match self.loop_count {
+                    0 => 0,
+                    1 => -1,
+                    n => n as i64 - 1,
+                };
+                args.extend(["-loop".into(), repeats.to_string()]);
+                // paletteuse already produced the palettized frames
+                return args;
+            }
+            VideoCodec::WebP => {
+                args.extend(["-preset".into(), self.preset.into()]);
+                args.extend(["-loop".into(), self.loop_count.to_string()]);
+                "yuv420p"
+            }
+        };
+
+        args.extend(["-threads".into(), "0".into(), "-pix_fmt".into(), pix_fmt.into()]);
+
+        // Put the index first so browsers can start playback before the download finishes
+        if matches!(self.container, Container::Mp4 | Container::Mov) {
+            args.extend(["-movflags".into(), "+faststart".into()]);
+        }
+
+        args
+    }
+}
+
+/// Size, frame rate and loop limits of GIF and WebP output
+fn check_animation_limits(req: &CreateTimelapseRequest) -> Result<(), String> {
+    let too_big = req.resolution.is_some_and(|preset| preset.sides().0 > ANIMATED_MAX_SIDE)
+        || [req.width, req.height].into_iter().flatten().any(|side| side > ANIMATED_MAX_SIDE);
+    if too_big {
+        return Err(format!(
+            "GIF and WebP output can be at most {} pixels on the long side",
+            ANIMATED_MAX_SIDE
+        ));
+    }
+
+    if req.interpolation.as_ref().is_some_and(|i| i.fps > ANIMATED_MAX_FPS) {
+        return Err(format!("GIF and WebP output can play at most {} fps", ANIMATED_MAX_FPS));
+    }
+
+    if req.loop_count.is_some_and(|n| n > MAX_LOOP_COUNT) {
+        return Err(format!("loopCount must be at most {}", MAX_LOOP_COUNT));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,210 @@
+use std::path::{Path, PathBuf};
+use anyhow::{Result, Context};
+use image::{DynamicImage, ImageFormat as EncodedFormat, ImageReader};
+use serde::{Deserialize, Serialize};
+use tokio_util::sync::CancellationToken;
+
+/// Side length frames are shrunk to before measuring their brightness
+const MEASURE_SIZE: u32 = 256;
+
+/// JPEG quality for corrected frames
+const JPEG_QUALITY: u8 = 95;
+
+/// Frames whose gain is this close to 1 are used as they are
+const GAIN_EPSILON: f64 = 0.005;
+
+/// Corrections are limited to one stop either way, so a black or blown-out
+/// frame can't be pushed into noise
+const MIN_GAIN: f64 = 0.5;
+const MAX_GAIN: f64 = 2.0;
+
+const MAX_WINDOW: usize = 101;
+
+fn default_strength() -> f64 {
+    1.0
+}
+
+fn default_window() -> usize {
+    15
+}
+
+/// Luminance normalization settings of a render request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Deflicker {
+    /// How far each frame is pulled towards the smoothed brightness, 0 to 1
+    #[serde(default = "default_strength")]
+    pub strength: f64,
+    /// Number of frames the brightness is averaged over; odd, 3 to 101
+    #[serde(default = "default_window")]
+    pub window: usize,
+}
+
+impl Deflicker {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(0.0..=1.0).contains(&self.strength) {
+            return Err("Deflicker strength must be between 0 and 1".to_string());
+        }
+        if !(3..=MAX_WINDOW).contains(&self.window) || self.window % 2 != 1 {
+            return Err(format!("Deflicker window must be an odd number between 3 and {}", MAX_WINDOW));
+        }
+        Ok(())
+    }
+}
+
+/// Mean Rec. 709 luma of a frame, 0 to 255, measured on a thumbnail
+fn mean_luma(image: &DynamicImage) -> f64 {
+    let luma = image.thumbnail(MEASURE_SIZE, MEASURE_SIZE).to_luma8();
+    let pixels = luma.as_raw();
+    if pixels.is_empty() {
+        return 0.0;
+    }
+    pixels.iter().map(|p| *p as f64).sum::<f64>() / pixels.len() as f64
+}
+
+/// Centered moving average, with the window shrinking at the ends
+fn smooth(values: &[f64], window: usize) -> Vec<f64> {
+    let half = window / 2;
+    (0..values.len())
+        .map(|i| {
+            let start = i.saturating_sub(half);
+            let end = (i + half + 1).min(values.len());
+            values[start..end].iter().sum::<f64>() / (end - start) as f64
+        })
+        .collect()
+}
+
+/// Gain that moves each frame's brightness towards the smoothed curve
+fn frame_gains(brightness: &[f64], settings: &Deflicker) -> Vec<f64> {
+    smooth(brightness, settings.window)
+        .iter()
+        .zip(brightness)
+        .map(|(target, actual)| {
+            if *actual <= 0.0 {
+                return 1.0;
+            }
+            let full = (target / actual).clamp(MIN_GAIN, MAX_GAIN);
+            1.0 + settings.strength * (full - 1.0)
+        })
+        .collect()
+}
+
+fn open_frame(path: &Path) -> Result<DynamicImage> {
+    ImageReader::open(path)
+        .with_context(|| format!("Failed to open {}", path.display()))?
+        .with_guessed_format()?
+        .decode()
+        .with_context(|| format!("Failed to decode {}", path.display()))
+}
+
+/// Multiply every color channel by `gain`, keeping alpha
+fn apply_gain(image: DynamicImage, gain: f64) -> DynamicImage {
+    let has_alpha = image.color().has_alpha();
+    let mut rgba = image.into_rgba8();
+    for pixel in rgba.pixels_mut() {
+        for channel in &mut pixel.0[..3] {
+            *channel = (*channel as f64 * gain).round().clamp(0.0, 255.0) as u8;
+        }
+    }
+    let corrected = DynamicImage::ImageRgba8(rgba);
+    if has_alpha {
+        corrected
+    } else {
+        DynamicImage::ImageRgb8(corrected.into_rgb8())
+    }
+}
+
+/// Write a corrected frame next to the others, as PNG if the source was PNG
+/// and as high-quality JPEG otherwise
+fn save_frame(image: &DynamicImage, source: &Path, out_dir: &Path, index: usize) -> Result<PathBuf> {
+    let is_png = source
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("png"));
+
+    let path = if is_png {
+        let path = out_dir.join(format!("{:06}.png", index));
+        image.save_with_format(&path, EncodedFormat::Png)?;
+        path
+    } else {
+        let path = out_dir.join(format!("{:06}.jpg", index));
+        let file = std::fs::File::create(&path)?;
+        let encoder = image::codecs::jpeg::JpegEncoder::new_with_quality(
+            std::io::BufWriter::new(file),
+            JPEG_QUALITY,
+        );
+        image.to_rgb8().write_with_encoder(encoder)?;
+        path
+    };
+
+    Ok(path)
+}
+
+/// Even out frame-to-frame brightness changes.
+///
+/// Measures each frame's mean luminance, smooths it over a rolling window and
+/// writes frames that need a correction to `out_dir`. Returns the path to use
+/// for every frame, in order: the corrected copy, or the original when it
+/// already matches the curve. `on_progress` is called with the number of frames
+/// done out of twice the frame count, since every frame is read twice.
+pub fn deflicker_frames<F>(
+    frames: &[PathBuf],
+    out_dir: &Path,
+    settings: &Deflicker,
+    cancel: &CancellationToken,
+    mut on_progress: F,
+) -> Result<Vec<PathBuf>>
+where
+    F: FnMut(usize),
+{
+    let mut brightness = Vec::with_capacity(frames.len());
+    for (i, path) in frames.iter().enumerate() {
+        if cancel.is_cancelled() {
+            anyhow::bail!("Render was cancelled");
+        }
+        brightness.push(mean_luma(&open_frame(path)?));
+        on_progress(i + 1);
+    }
+
+    let gains = frame_gains(&brightness, settings);
+
+    let mut output = Vec::with_capacity(frames.len());
+    for (i, (path, gain)) in frames.iter().zip(gains).enumerate() {
+        if cancel.is_cancelled() {
+            anyhow::bail!("Render was cancelled");
+        }
+        if (gain - 1.0).abs() < GAIN_EPSILON {
+            output.push(path.clone());
+        } else {
+            let corrected = apply_gain(open_frame(path)?, gain);
+            output.push(save_frame(&corrected, path, out_dir, i)?);
+        }
+        on_progress(frames.len() + i + 1);
+    }
+
+    Ok(output)
+}
+
+/// Deflicker one frame the way [`deflicker_frames`] would, for a preview.
+///
+/// A frame's gain only depends on the brightness of the frames within half a
+/// window of it, so only those are measured. Returns the corrected copy in
+/// `out_dir`, or the original when it needs no correction.
+pub fn deflicker_frame(frames: &[PathBuf], index: usize, out_dir: &Path, settings: &Deflicker) -> Result<PathBuf> {
+    let half = settings.window / 2;
+    let start = index.saturating_sub(half);
+    let end = (index + half + 1).min(frames.len());
+
+    let brightness = frames[start..end]
+        .iter()
+        .map(|path| open_frame(path).map(|image| mean_luma(&image)))
+        .collect::<Result<Vec<_>>>()?;
+    let gain = frame_gains(&brightness, settings)[index - start];
+
+    let path = &frames[index];
+    if (gain - 1.0).abs() < GAIN_EPSILON {
+        Ok(path.clone())
+    } else {
+        let corrected = apply_gain(open_frame(path)?, gain);
+        save_frame(&corrected, path, out_dir, index)
+    }
+}
@@ -0,0 +1,68 @@
+use serde::{Deserialize, Serialize};
+use crate::models::CreateTimelapseRequest;
+use crate::video::codec::{Container, VideoCodec};
+
+/// Drafts are shrunk to fit a square of this side
+pub const DRAFT_MAX_SIDE: u32 = 640;
+
+/// H.264 CRF of drafts; blocky, but enough to judge pacing
+const DRAFT_QUALITY: u32 = 32;
+
+const DRAFT_PRESET: &str = "ultrafast";
+
+/// Largest draft frame step
+const MAX_DRAFT_FRAME_STEP: usize = 100;
+
+fn default_frame_step() -> usize {
+    1
+}
+
+/// Settings of a quick, low-quality render for checking pacing before the
+/// full-quality encode
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Draft {
+    /// Only every Nth of the video's frames is encoded, each shown for as long
+    /// as the frames it stands in for
+    #[serde(default = "default_frame_step")]
+    pub frame_step: usize,
+}
+
+impl Draft {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(1..=MAX_DRAFT_FRAME_STEP).contains(&self.frame_step) {
+            return Err(format!("Draft frameStep must be between 1 and {}", MAX_DRAFT_FRAME_STEP));
+        }
+        Ok(())
+    }
+
+    /// The request's settings as rendered for the draft: small H.264 with the
+    /// fastest preset, keeping the frames, order and timing but none of the
+    /// slow passes or extra outputs
+    pub fn apply(&self, base: &CreateTimelapseRequest) -> CreateTimelapseRequest {
+        let mut settings = base.clone();
+        settings.codec = VideoCodec::H264;
+        settings.container = Some(Container::Mp4);
+        settings.quality = Some(DRAFT_QUALITY);
+        settings.preset = Some(DRAFT_PRESET.to_string());
+        settings.loop_count = None;
+        settings.deflicker = None;
+        settings.stabilize = None;
+        settings.interpolation = None;
+        settings.renditions = None;
+        settings
+    }
+
+    /// Keep every `frame_step`th frame, each lasting the combined duration of
+    /// the frames it replaces, so the draft is exactly as long as the video
+    pub fn thin_frames(&self, files: Vec<String>, durations: &[f64]) -> (Vec<String>, Vec<f64>) {
+        let files = files.into_iter().step_by(self.frame_step).collect();
+        let durations = durations.chunks(self.frame_step).map(|chunk| chunk.iter().sum()).collect();
+        (files, durations)
+    }
+
+    /// Number of frames in the draft of a video with `frame_count` frames
+    pub fn frame_count(&self, frame_count: usize) -> usize {
+        frame_count.div_ceil(self.frame_step)
+    }
+}
@@ -0,0 +1,209 @@
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use crate::models::CreateTimelapseRequest;
+use crate::video::codec::ANIMATED_DEFAULT_SIDE;
+use crate::video::draft::DRAFT_MAX_SIDE;
+use crate::video::rotation::get_rotation_filter;
+
+/// Largest output width or height accepted in a render request
+const MAX_DIMENSION: u32 = 8192;
+
+/// Common output resolutions; the box follows the frames' orientation, so
+/// portrait frames at 1080p become 1080x1920
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ResolutionPreset {
+    #[serde(rename = "720p")]
+    Hd,
+    #[serde(rename = "1080p")]
+    FullHd,
+    #[serde(rename = "1440p")]
+    Qhd,
+    #[serde(rename = "4k")]
+    Uhd,
+}
+
+impl ResolutionPreset {
+    /// Long and short side in pixels
+    pub fn sides(&self) -> (u32, u32) {
+        match self {
+            ResolutionPreset::Hd => (1280, 720),
+            ResolutionPreset::FullHd => (1920, 1080),
+            ResolutionPreset::Qhd => (2560, 1440),
+            ResolutionPreset::Uhd => (3840, 2160),
+        }
+    }
+}
+
+/// How frames are fitted into an output size with a different aspect ratio
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FitMode {
+    /// Scale to fit inside and pad the rest with black bars
+    #[default]
+    Letterbox,
+    /// Scale to cover and cut off what sticks out
+    Crop,
+    /// Scale to the exact size, distorting the image
+    Stretch,
+}
+
+/// Region of the source frames to keep, in pixels of the unrotated frame
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CropRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Output size as FFmpeg expressions over the input size
+enum TargetSize {
+    /// Both sides fixed; the fit mode decides how the frame fills them
+    Box { width: String, height: String },
+    /// One side fixed, the other follows the aspect ratio
+    Width(u32),
+    Height(u32),
+    /// Shrink to fit a square of this side, never enlarging
+    Within(u32),
+}
+
+fn target_size(req: &CreateTimelapseRequest) -> Option<TargetSize> {
+    // Drafts are for judging pacing, so they ignore the output size
+    if req.draft.is_some() {
+        return Some(TargetSize::Within(DRAFT_MAX_SIDE));
+    }
+
+    if let Some(preset) = req.resolution {
+        let (long, short) = preset.sides();
+        return Some(TargetSize::Box {
+            width: format!("if(gt(iw,ih),{},{})", long, short),
+            height: format!("if(gt(iw,ih),{},{})", short, long),
+        });
+    }
+
+    match (req.width, req.height) {
+        (Some(width), Some(height)) => Some(TargetSize::Box {
+            width: width.to_string(),
+            height: height.to_string(),
+        }),
+        (Some(width), None) => Some(TargetSize::Width(width)),
+        (None, Some(height)) => Some(TargetSize::Height(height)),
+        // Full-size animated images are far too heavy
+        (None, None) if req.codec.is_animated_image() => Some(TargetSize::Within(ANIMATED_DEFAULT_SIDE)),
+        (None, None) => None,
+    }
+}
+
+/// Check the resolution, fit and crop settings of a render request
+pub fn validate_geometry(req: &CreateTimelapseRequest) -> Result<(), String> {
+    if req.resolution.is_some() && (req.width.is_some() || req.height.is_some()) {
+        return Err("Give either a resolution preset or width/height, not both".to_string());
+    }
+
+    for side in [req.width, req.height].into_iter().flatten() {
+        // yuv420p needs even dimensions
+        if !(16..=MAX_DIMENSION).contains(&side) || side % 2 != 0 {
+            return Err(format!(
+                "Width and height must be even numbers between 16 and {}",
+                MAX_DIMENSION
+            ));
+        }
+    }
+
+    if let Some(crop) = &req.crop {
+        // yuv420p needs even dimensions, and uncropped frames may not be scaled
+        if crop.width == 0 || crop.height == 0 || crop.width % 2 != 0 || crop.height % 2 != 0 {
+            return Err("Crop width and height must be even numbers greater than 0".to_string());
+        }
+    }
+
+    Ok(())
+}
+
+/// Check the crop rectangle against the size of `frame`, the first frame of the
+/// video, which FFmpeg would otherwise only reject partway into the render
+pub fn validate_crop_bounds(req: &CreateTimelapseRequest, frame: &Path) -> Result<(), String> {
+    let Some(crop) = &req.crop else {
+        return Ok(());
+    };
+
+    let (width, height) = image::image_dimensions(frame)
+        .map_err(|e| format!("Failed to read the size of the first frame: {}", e))?;
+    if crop.x as u64 + crop.width as u64 > width as u64 || crop.y as u64 + crop.height as u64 > height as u64 {
+        return Err(format!("Crop rectangle must fit inside the {}x{} frames", width, height));
+    }
+
+    Ok(())
+}
+
+/// Crop and rotation filters, which work on the source frames before anything
+/// else sees them
+pub fn source_filters(req: &CreateTimelapseRequest) -> Vec<String> {
+    let mut filters = Vec::new();
+
+    if let Some(crop) = &req.crop {
+        filters.push(format!("crop={}:{}:{}:{}", crop.width, crop.height, crop.x, crop.y));
+    }
+
+    if let Some(rotation) = get_rotation_filter(req.rotation) {
+        filters.push(rotation);
+    }
+
+    filters
+}
+
+/// FFmpeg `-vf` chain for a render: crop, then rotation, then `stabilization`
+/// if given, then scaling to the output size, then frame interpolation. `None`
+/// when the frames are used as they are.
+pub fn build_filter_chain(req: &CreateTimelapseRequest, stabilization: Option<String>) -> Option<String> {
+    let mut filters = source_filters(req);
+    filters.extend(stabilization);
+
+    let target = target_size(req);
+    let scaled = target.is_some();
+    match target {
+        Some(TargetSize::Box { width, height }) => match req.fit {
+            FitMode::Letterbox => {
+                filters.push(format!(
+                    "scale=w='{w}':h='{h}':force_original_aspect_ratio=decrease:force_divisible_by=2:flags=lanczos",
+                    w = width, h = height
+                ));
+                filters.push(format!("pad=w='{w}':h='{h}':x=(ow-iw)/2:y=(oh-ih)/2:color=black", w = width, h = height));
+            }
+            FitMode::Crop => {
+                filters.push(format!(
+                    "scale=w='{w}':h='{h}':force_original_aspect_ratio=increase:flags=lanczos",
+                    w = width, h = height
+                ));
+                filters.push(format!("crop=w='{w}':h='{h}'", w = width, h = height));
+            }
+            FitMode::Stretch => {
+                filters.push(format!("scale=w='{w}':h='{h}':flags=lanczos", w = width, h = height));
+            }
+        },
+        Some(TargetSize::Width(width)) => filters.push(format!("scale=w={}:h=-2:flags=lanczos", width)),
+        Some(TargetSize::Height(height)) => filters.push(format!("scale=w=-2:h={}:flags=lanczos", height)),
+        Some(TargetSize::Within(side)) => filters.push(format!(
+            "scale=w='min(iw,{s})':h='min(ih,{s})':force_original_aspect_ratio=decrease:force_divisible_by=2:flags=lanczos",
+            s = side
+        )),
+        None => {}
+    }
+
+    // Scaling can leave a non-square sample aspect ratio that players honour
+    if scaled {
+        filters.push("setsar=1".to_string());
+    }
+
+    // Synthesize in-between frames last, at the output size, where it's cheapest
+    if let Some(interpolation) = &req.interpolation {
+        filters.push(interpolation.filter());
+    }
+
+    if filters.is_empty() {
+        None
+    } else {
+        Some(filters.join(","))
+    }
+}
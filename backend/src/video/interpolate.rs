@@ -0,0 +1,56 @@
+use serde::{Deserialize, Serialize};
+
+/// Highest frame rate frames can be interpolated up to
+const MAX_OUTPUT_FPS: f64 = 120.0;
+
+fn default_output_fps() -> f64 {
+    60.0
+}
+
+/// How in-between frames are synthesized
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InterpolationMode {
+    /// Crossfade neighbouring frames; fast, but moving objects ghost
+    Blend,
+    /// Motion-compensated interpolation; slow, but moving objects stay sharp
+    #[default]
+    Motion,
+}
+
+/// Frame interpolation settings of a render request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Interpolation {
+    /// Frame rate of the output video, up to 120
+    #[serde(default = "default_output_fps")]
+    pub fps: f64,
+    #[serde(default)]
+    pub mode: InterpolationMode,
+}
+
+impl Interpolation {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.fps >= 1.0 && self.fps <= MAX_OUTPUT_FPS) {
+            return Err(format!("Interpolation fps must be between 1 and {}", MAX_OUTPUT_FPS));
+        }
+        Ok(())
+    }
+
+    /// FFmpeg `minterpolate` filter producing the output frame rate
+    pub fn filter(&self) -> String {
+        match self.mode {
+            InterpolationMode::Blend => format!("minterpolate=fps={}:mi_mode=blend", self.fps),
+            InterpolationMode::Motion => format!(
+                "minterpolate=fps={}:mi_mode=mci:mc_mode=aobmc:me_mode=bidir:vsbmc=1",
+                self.fps
+            ),
+        }
+    }
+
+    /// Number of frames the encoder receives for a video whose frames are
+    /// shown for `durations` seconds each
+    pub fn output_frame_count(&self, durations: &[f64]) -> u32 {
+        (durations.iter().sum::<f64>() * self.fps).round() as u32
+    }
+}
@@ -1,2 +1,11 @@
+pub mod codec;
+pub mod deflicker;
+pub mod draft;
+pub mod filters;
+pub mod interpolate;
+pub mod pacing;
 pub mod processor;
+pub mod queue;
+pub mod rendition;
 pub mod rotation;
+pub mod stabilize;
@@ -0,0 +1,365 @@
+use serde::{Deserialize, Serialize};
+use crate::models::CreateTimelapseRequest;
+
+/// Frames slowed down by default at the end of a video
+const DEFAULT_SLOW_ENDING_FRAMES: usize = 5;
+
+/// FPS for slowed frames (2 fps = 0.5 seconds per frame)
+const DEFAULT_SLOW_FPS: f64 = 2.0;
+
+/// Same upper bound as the video frame rate itself
+const MAX_SLOW_FPS: f64 = 60.0;
+
+/// Longest a last frame can be held
+const MAX_HOLD_SECONDS: f64 = 60.0;
+
+/// Lowest frame rate a video is rendered at
+pub const MIN_FPS: f64 = 1.0;
+
+/// Highest frame rate a video is rendered at
+pub const MAX_FPS: f64 = 60.0;
+
+/// Longest video a target duration may ask for
+const MAX_TARGET_DURATION_SECONDS: f64 = 3600.0;
+
+fn default_slow_fps() -> f64 {
+    DEFAULT_SLOW_FPS
+}
+
+/// A run of frames at the start or end of the video shown at their own frame rate
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SlowMotion {
+    /// Number of frames slowed down; 0 disables the effect
+    pub frames: usize,
+    #[serde(default = "default_slow_fps")]
+    pub fps: f64,
+}
+
+impl SlowMotion {
+    /// The slowed tail every video had before it was configurable
+    pub fn default_ending() -> Self {
+        Self { frames: DEFAULT_SLOW_ENDING_FRAMES, fps: DEFAULT_SLOW_FPS }
+    }
+
+    pub fn disabled() -> Self {
+        Self { frames: 0, fps: DEFAULT_SLOW_FPS }
+    }
+
+    fn validate(&self, name: &str) -> Result<(), String> {
+        if self.frames > 0 && !(self.fps > 0.0 && self.fps <= MAX_SLOW_FPS) {
+            return Err(format!("{} fps must be greater than 0 and at most {}", name, MAX_SLOW_FPS));
+        }
+        Ok(())
+    }
+}
+
+/// Check the slow-in, slow ending and hold settings of a render request
+pub fn validate_pacing(req: &CreateTimelapseRequest) -> Result<(), String> {
+    req.slow_start.validate("Slow start")?;
+    req.slow_ending.validate("Slow ending")?;
+
+    if !(0.0..=MAX_HOLD_SECONDS).contains(&req.hold_last_seconds) {
+        return Err(format!("holdLastSeconds must be between 0 and {}", MAX_HOLD_SECONDS));
+    }
+    Ok(())
+}
+
+/// Duration of frame `index` of `frame_count` if it is slowed down, `None` if it
+/// plays at the normal frame rate. Where the slow start and slow ending overlap
+/// on short frame sets, the ending wins.
+fn slow_duration(index: usize, frame_count: usize, req: &CreateTimelapseRequest) -> Option<f64> {
+    let slow_ending_from = frame_count.saturating_sub(req.slow_ending.frames);
+    if req.slow_ending.frames > 0 && index >= slow_ending_from {
+        Some(1.0 / req.slow_ending.fps)
+    } else if index < req.slow_start.frames {
+        Some(1.0 / req.slow_start.fps)
+    } else {
+        None
+    }
+}
+
+/// How long each of `frame_count` frames is shown, in seconds.
+///
+/// The hold time is added to the last frame.
+pub fn frame_durations(frame_count: usize, req: &CreateTimelapseRequest) -> Vec<f64> {
+    let normal_duration = 1.0 / req.fps;
+
+    let mut durations: Vec<f64> = (0..frame_count)
+        .map(|i| slow_duration(i, frame_count, req).unwrap_or(normal_duration))
+        .collect();
+
+    if let Some(last) = durations.last_mut() {
+        *last += req.hold_last_seconds;
+    }
+
+    durations
+}
+
+/// Keep every `step`th frame, always including the last one so the video ends
+/// where the frame set does
+pub fn step_frames(files: Vec<String>, step: usize) -> Vec<String> {
+    if step <= 1 {
+        return files;
+    }
+
+    let last = files.len().saturating_sub(1);
+    files
+        .into_iter()
+        .enumerate()
+        .filter(|(i, _)| i % step == 0 || *i == last)
+        .map(|(_, f)| f)
+        .collect()
+}
+
+/// Number of frames [`step_frames`] keeps out of `frame_count`
+pub fn stepped_frame_count(frame_count: usize, step: usize) -> usize {
+    if frame_count == 0 || step <= 1 {
+        return frame_count;
+    }
+    let last = frame_count - 1;
+    let kept = last / step + 1;
+    // The last frame is added when the step doesn't land on it
+    if last / step * step == last { kept } else { kept + 1 }
+}
+
+/// Frame rate and frame step that make a render last a target duration
+#[derive(Debug, Clone, Copy)]
+pub struct DurationFit {
+    pub fps: f64,
+    pub frame_step: usize,
+}
+
+/// Work out the frame rate at which `frame_count` frames, with the request's
+/// slow-motion and hold settings, last `target_seconds`. When that rate would
+/// exceed `max_fps`, frames are skipped instead: only every `frame_step`th
+/// frame is rendered. Targets that would need fewer than [`MIN_FPS`] are
+/// rejected, as they are when `fps` is given directly.
+pub fn fit_to_duration(
+    frame_count: usize,
+    req: &CreateTimelapseRequest,
+    target_seconds: f64,
+    max_fps: f64,
+) -> Result<DurationFit, String> {
+    if !(target_seconds > 0.0 && target_seconds <= MAX_TARGET_DURATION_SECONDS) {
+        return Err(format!(
+            "targetDurationSeconds must be greater than 0 and at most {}",
+            MAX_TARGET_DURATION_SECONDS
+        ));
+    }
+
+    for frame_step in 1..=frame_count.max(1) {
+        let kept = stepped_frame_count(frame_count, frame_step);
+
+        // Slowed frames and the hold take a fixed amount of time whatever the fps
+        let mut fixed_seconds = req.hold_last_seconds;
+        let mut normal_frames = 0;
+        for i in 0..kept {
+            match slow_duration(i, kept, req) {
+                Some(duration) => fixed_seconds += duration,
+                None => normal_frames += 1,
+            }
+        }
+
+        let available = target_seconds - fixed_seconds;
+        if available <= 0.0 {
+            return Err(format!(
+                "Target duration is shorter than the {:.1}s of slow motion and hold",
+                fixed_seconds
+            ));
+        }
+        if normal_frames == 0 {
+            return Err("Every frame is slowed down, so the duration can't be adjusted".to_string());
+        }
+
+        let fps = normal_frames as f64 / available;
+        if fps < MIN_FPS {
+            return Err(format!(
+                "Target duration is too long for this many frames: they would play at {:.2} fps, below the minimum of {}",
+                fps, MIN_FPS
+            ));
+        }
+        if fps <= max_fps {
+            return Ok(DurationFit { fps, frame_step });
+        }
+    }
+
+    Err("Target duration is too short for this many frames".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// A render request at 10 fps with `overrides` applied
+    fn request(overrides: serde_json::Value) -> CreateTimelapseRequest {
+        let mut value = json!({ "jobId": "job", "rotation": 0, "fps": 10.0 });
+        value.as_object_mut().unwrap().extend(overrides.as_object().unwrap().clone());
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn default_request_slows_down_the_last_five_frames() {
+        let durations = frame_durations(8, &request(json!({})));
+
+        assert_eq!(durations, [0.1, 0.1, 0.1, 0.5, 0.5, 0.5, 0.5, 0.5]);
+    }
+
+    #[test]
+    fn slow_start_and_disabled_ending() {
+        let req = request(json!({
+            "slowStart": { "frames": 2, "fps": 4.0 },
+            "slowEnding": { "frames": 0 }
+        }));
+
+        assert_eq!(frame_durations(4, &req), [0.25, 0.25, 0.1, 0.1]);
+    }
+
+    #[test]
+    fn slow_ending_wins_where_it_overlaps_the_slow_start() {
+        let req = request(json!({
+            "slowStart": { "frames": 3, "fps": 4.0 },
+            "slowEnding": { "frames": 2, "fps": 1.0 }
+        }));
+
+        assert_eq!(frame_durations(4, &req), [0.25, 0.25, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn hold_is_added_to_the_last_frame() {
+        let req = request(json!({ "slowEnding": { "frames": 0 }, "holdLastSeconds": 2.0 }));
+
+        assert_eq!(frame_durations(3, &req), [0.1, 0.1, 2.1]);
+        assert!(frame_durations(0, &req).is_empty());
+    }
+
+    #[test]
+    fn validate_pacing_checks_slow_fps_and_hold() {
+        assert!(validate_pacing(&request(json!({}))).is_ok());
+        assert!(validate_pacing(&request(json!({ "slowStart": { "frames": 2, "fps": 0.0 } }))).is_err());
+        // A disabled effect's frame rate doesn't matter
+        assert!(validate_pacing(&request(json!({ "slowEnding": { "frames": 0, "fps": 0.0 } }))).is_ok());
+        assert!(validate_pacing(&request(json!({ "holdLastSeconds": -1.0 }))).is_err());
+        assert!(validate_pacing(&request(json!({ "holdLastSeconds": MAX_HOLD_SECONDS + 1.0 }))).is_err());
+    }
+
+    fn no_slow_motion() -> CreateTimelapseRequest {
+        request(json!({ "slowEnding": { "frames": 0 } }))
+    }
+
+    fn names(count: usize) -> Vec<String> {
+        (0..count).map(|i| i.to_string()).collect()
+    }
+
+    #[test]
+    fn stepped_frame_count_matches_step_frames() {
+        for frame_count in 0..30 {
+            for step in 0..12 {
+                assert_eq!(
+                    stepped_frame_count(frame_count, step),
+                    step_frames(names(frame_count), step).len(),
+                    "{} frames, step {}",
+                    frame_count,
+                    step
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn step_frames_always_keeps_the_last_frame() {
+        assert_eq!(step_frames(names(10), 3), ["0", "3", "6", "9"]);
+        assert_eq!(step_frames(names(11), 3), ["0", "3", "6", "9", "10"]);
+        assert_eq!(step_frames(names(3), 10), ["0", "2"]);
+        assert_eq!(step_frames(names(1), 5), ["0"]);
+    }
+
+    #[test]
+    fn fit_to_duration_works_out_the_frame_rate() {
+        let fit = fit_to_duration(100, &no_slow_motion(), 10.0, MAX_FPS).unwrap();
+
+        assert_eq!(fit.frame_step, 1);
+        assert!((fit.fps - 10.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn fit_to_duration_leaves_slowed_frames_and_hold_alone() {
+        // The last 5 frames at 2 fps and a 1.5s hold take 4s of the 10
+        let req = request(json!({ "holdLastSeconds": 1.5 }));
+        let fit = fit_to_duration(65, &req, 10.0, MAX_FPS).unwrap();
+
+        assert_eq!(fit.frame_step, 1);
+        assert!((fit.fps - 10.0).abs() < 1e-9);
+        let total: f64 = frame_durations(65, &request(json!({ "holdLastSeconds": 1.5, "fps": fit.fps }))).iter().sum();
+        assert!((total - 10.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn fit_to_duration_steps_frames_above_the_frame_rate_limit() {
+        let fit = fit_to_duration(1000, &no_slow_motion(), 10.0, MAX_FPS).unwrap();
+
+        // Every other frame, plus the last, at just over 50 fps
+        assert_eq!(fit.frame_step, 2);
+        assert_eq!(stepped_frame_count(1000, 2), 501);
+        assert!((fit.fps - 50.1).abs() < 1e-9);
+    }
+
+    #[test]
+    fn fit_to_duration_uses_the_given_frame_rate_limit() {
+        let fit = fit_to_duration(100, &no_slow_motion(), 10.0, 5.0).unwrap();
+
+        // Every other frame keeps 51 with the last, just over the limit
+        assert_eq!(fit.frame_step, 3);
+        assert!((fit.fps - 3.4).abs() < 1e-9);
+    }
+
+    #[test]
+    fn fit_to_duration_handles_a_single_frame() {
+        let fit = fit_to_duration(1, &no_slow_motion(), 0.5, MAX_FPS).unwrap();
+
+        assert_eq!(fit.frame_step, 1);
+        assert!((fit.fps - 2.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn fit_to_duration_rejects_targets_below_the_minimum_frame_rate() {
+        // One frame for 5 seconds would play at 0.2 fps
+        let err = fit_to_duration(1, &no_slow_motion(), 5.0, MAX_FPS).unwrap_err();
+        assert!(err.contains("too long"), "{}", err);
+
+        let fit = fit_to_duration(10, &no_slow_motion(), 10.0, MAX_FPS).unwrap();
+        assert!((fit.fps - MIN_FPS).abs() < 1e-9);
+    }
+
+    #[test]
+    fn fit_to_duration_rejects_out_of_range_targets() {
+        for target in [0.0, -1.0, f64::NAN, MAX_TARGET_DURATION_SECONDS + 1.0] {
+            assert!(fit_to_duration(100, &no_slow_motion(), target, MAX_FPS).is_err(), "{}", target);
+        }
+        assert!(fit_to_duration(10_000, &no_slow_motion(), MAX_TARGET_DURATION_SECONDS, MAX_FPS).is_ok());
+    }
+
+    #[test]
+    fn fit_to_duration_rejects_targets_shorter_than_slow_motion() {
+        // The default slow ending alone takes 2.5s
+        let err = fit_to_duration(100, &request(json!({})), 2.5, MAX_FPS).unwrap_err();
+
+        assert!(err.contains("shorter than"), "{}", err);
+    }
+
+    #[test]
+    fn fit_to_duration_rejects_fully_slowed_videos() {
+        let err = fit_to_duration(3, &request(json!({})), 10.0, MAX_FPS).unwrap_err();
+
+        assert!(err.contains("Every frame is slowed down"), "{}", err);
+    }
+
+    #[test]
+    fn fit_to_duration_rejects_targets_too_short_for_any_step() {
+        // Even a single frame would have to play faster than the limit
+        let err = fit_to_duration(100, &no_slow_motion(), 0.01, MAX_FPS).unwrap_err();
+
+        assert!(err.contains("too short"), "{}", err);
+    }
+}
@@ -1,23 +1,22 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
-use std::sync::{Arc, Mutex};
-use std::collections::HashMap;
 use std::fs;
+use std::time::Instant;
 use anyhow::{Result, Context};
 use tokio::io::{AsyncBufReadExt, BufReader};
 use tokio::process::Command as TokioCommand;
+use tokio_util::sync::CancellationToken;
 use regex::Regex;
-use crate::video::rotation::get_rotation_filter;
+use crate::frames::ordering::order_frames;
+use crate::frames::selection::select_frames;
+use crate::frames::validate::validate_frame;
+use crate::video::codec::Encoding;
+use crate::video::deflicker::deflicker_frames;
+use crate::video::filters::{build_filter_chain, source_filters};
+use crate::video::pacing::{frame_durations, step_frames};
 use crate::storage::local::list_image_files;
-use crate::models::{JobStatusType, ProcessingProgress};
-
-type JobStore = Arc<Mutex<HashMap<String, JobStatusType>>>;
-
-/// Number of frames at the end to show in slow-motion
-const SLOW_ENDING_FRAMES: usize = 5;
-
-/// FPS for the slow-motion ending (2 fps = 0.5 seconds per frame)
-const SLOW_ENDING_FPS: f64 = 2.0;
+use crate::models::{CreateTimelapseRequest, ProcessingProgress};
+use crate::storage::job_store::JobStore;
 
 /// Parse frame number from FFmpeg stderr line
 /// FFmpeg outputs lines like: frame=  123 fps= 30 q=28.0 size=    1024kB time=00:00:04.10
@@ -29,95 +28,317 @@ fn parse_frame_from_line(line: &str) -> Option<u32> {
         .and_then(|m| m.as_str().parse::<u32>().ok())
 }
 
-/// Update job progress in the store
-fn update_job_progress(
-    job_store: &JobStore,
-    job_id: &str,
+/// One file a render writes
+pub struct RenderOutput {
+    /// Rendition id, for renders that produce several
+    pub rendition: Option<String>,
+    pub path: PathBuf,
+    /// The render settings with this output's codec and size applied
+    pub settings: CreateTimelapseRequest,
+}
+
+/// Reports a render's progress to the job store
+#[derive(Clone)]
+struct ProgressReporter {
+    job_store: JobStore,
+    job_id: String,
+    /// Rendition being encoded, for renders that produce several
+    rendition: Option<String>,
+    completed_renditions: Vec<String>,
+}
+
+impl ProgressReporter {
+    /// Update job progress in the store
+    fn update(&self, stage: &str, current_frame: u32, total_frames: u32, eta_seconds: Option<u64>) {
+        let percent = if total_frames > 0 {
+            ((current_frame as f32 / total_frames as f32) * 100.0).min(99.0) as u8
+        } else {
+            0
+        };
+
+        let progress = ProcessingProgress {
+            stage: stage.to_string(),
+            current_frame,
+            total_frames,
+            percent,
+            eta_seconds,
+            rendition: self.rendition.clone(),
+            completed_renditions: self.completed_renditions.clone(),
+        };
+
+        self.job_store.set_progress(&self.job_id, progress);
+    }
+}
+
+/// Extrapolate the remaining encoding time from the frames encoded so far
+fn estimate_remaining_seconds(started: Instant, current_frame: u32, total_frames: u32) -> Option<u64> {
+    if current_frame == 0 {
+        return None;
+    }
+    let remaining_frames = total_frames.saturating_sub(current_frame) as f64;
+    let seconds_per_frame = started.elapsed().as_secs_f64() / current_frame as f64;
+    Some((remaining_frames * seconds_per_frame).ceil() as u64)
+}
+
+/// FFmpeg command reading the frames listed in a concat demuxer file.
+///
+/// We don't set -r before -i because we use duration directives in the file
+/// list for the slow start and ending. The durations control frame display time.
+fn concat_command(list_file_path: &Path) -> TokioCommand {
+    let mut cmd = TokioCommand::new("ffmpeg");
+    cmd.arg("-f")
+        .arg("concat")
+        .arg("-safe")
+        .arg("0")
+        .arg("-i")
+        .arg(list_file_path);
+    cmd
+}
+
+/// Run an FFmpeg pass to completion, reporting the frames it has processed as
+/// `stage` progress. FFmpeg is killed if the job is cancelled.
+async fn run_ffmpeg(
+    mut cmd: TokioCommand,
+    progress: &ProgressReporter,
     stage: &str,
-    current_frame: u32,
     total_frames: u32,
-) {
-    let percent = if total_frames > 0 {
-        ((current_frame as f32 / total_frames as f32) * 100.0).min(99.0) as u8
-    } else {
-        0
-    };
+    cancel: &CancellationToken,
+) -> Result<()> {
+    // Set up stderr capture for progress
+    cmd.stderr(Stdio::piped());
+    cmd.stdout(Stdio::null());
+    cmd.kill_on_drop(true);
 
-    let progress = ProcessingProgress {
-        stage: stage.to_string(),
-        current_frame,
-        total_frames,
-        percent,
-    };
+    progress.update(stage, 0, total_frames, None);
+
+    // Spawn the process
+    let mut child = cmd.spawn().context("Failed to spawn FFmpeg process")?;
+    let started = Instant::now();
+
+    // Read stderr for progress
+    let stderr = child.stderr.take().expect("Failed to capture stderr");
+    let reader = BufReader::new(stderr);
+    let mut lines = reader.lines();
+
+    // Process stderr lines for progress updates until FFmpeg exits or the job is cancelled
+    loop {
+        tokio::select! {
+            line = lines.next_line() => match line {
+                Ok(Some(line)) => {
+                    if let Some(frame) = parse_frame_from_line(&line) {
+                        let eta = estimate_remaining_seconds(started, frame, total_frames);
+                        progress.update(stage, frame, total_frames, eta);
+                    }
+                }
+                _ => break,
+            },
+            _ = cancel.cancelled() => {
+                let _ = child.kill().await;
+                anyhow::bail!("Render was cancelled");
+            }
+        }
+    }
 
-    if let Ok(mut store) = job_store.lock() {
-        store.insert(job_id.to_string(), JobStatusType::Processing(Some(progress)));
+    // Wait for the process to complete
+    let status = child.wait().await.context("Failed to wait for FFmpeg")?;
+    if !status.success() {
+        anyhow::bail!("FFmpeg failed with exit code {:?}", status.code());
     }
+
+    Ok(())
 }
 
-/// Async version of create_timelapse with real-time progress streaming
-pub async fn create_timelapse_async(
+/// A job's frames in render order, after the request's selection rules but
+/// before its frame step
+pub fn frames_for_render(
     job_id: &str,
-    frames_dir: PathBuf,
-    output_path: PathBuf,
-    fps: u32,
-    rotation: u32,
-    job_store: JobStore,
-) -> Result<()> {
-    // Update status to preparing
-    {
-        let progress = ProcessingProgress {
-            stage: "preparing".to_string(),
-            current_frame: 0,
-            total_frames: 0,
-            percent: 0,
-        };
-        if let Ok(mut store) = job_store.lock() {
-            store.insert(job_id.to_string(), JobStatusType::Processing(Some(progress)));
-        }
+    frames_dir: &Path,
+    settings: &CreateTimelapseRequest,
+) -> Result<Vec<String>> {
+    let ordered = order_frames(
+        frames_dir,
+        list_image_files(job_id)?,
+        settings.order,
+        settings.explicit_order.as_deref(),
+    )?;
+
+    Ok(match &settings.selection {
+        Some(selection) => select_frames(frames_dir, ordered, selection),
+        None => ordered,
+    })
+}
+
+/// Render one frame through an output's filter chain as a PNG, the way it
+/// appears in the video.
+///
+/// Stabilization and interpolation need the whole sequence and the GIF palette
+/// is built from every frame, so the preview leaves them out.
+pub async fn render_still(frame_path: &Path, settings: &CreateTimelapseRequest) -> Result<Vec<u8>> {
+    let mut settings = settings.clone();
+    settings.interpolation = None;
+
+    let mut cmd = TokioCommand::new("ffmpeg");
+    cmd.arg("-v").arg("error").arg("-i").arg(frame_path);
+    if let Some(filters) = build_filter_chain(&settings, None) {
+        cmd.arg("-vf").arg(filters);
+    }
+    cmd.arg("-frames:v")
+        .arg("1")
+        .arg("-f")
+        .arg("image2pipe")
+        .arg("-c:v")
+        .arg("png")
+        .arg("-")
+        .stdin(Stdio::null())
+        .kill_on_drop(true);
+
+    let output = cmd.output().await.context("Failed to spawn FFmpeg process")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "FFmpeg failed with exit code {:?}: {}",
+            output.status.code(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    if output.stdout.is_empty() {
+        anyhow::bail!("FFmpeg produced no image");
     }
 
-    // Get rotation filter if needed
-    let rotation_filter = get_rotation_filter(rotation);
+    Ok(output.stdout)
+}
 
-    // Get sorted list of image files
-    let image_files = list_image_files(job_id)?;
+/// The frames a render encodes, with how long each is shown: selected, ordered
+/// and stepped, thinned for a draft, and checked to decode
+fn prepare_frames(
+    job_id: &str,
+    frames_dir: &Path,
+    settings: &CreateTimelapseRequest,
+) -> Result<(Vec<String>, Vec<f64>)> {
+    // Get the selected frames in the requested order, skipping any the frame step leaves out
+    let image_files = step_frames(
+        frames_for_render(job_id, frames_dir, settings)?,
+        settings.frame_step,
+    );
     if image_files.is_empty() {
         anyhow::bail!("No image files found");
     }
 
-    let total_frames = image_files.len() as u32;
+    // Per-frame durations for the slow start, slow ending and hold. A draft
+    // thins the frames out further but keeps the video's timing
+    let durations = frame_durations(image_files.len(), settings);
+    let (image_files, durations) = match &settings.draft {
+        Some(draft) => draft.thin_frames(image_files, &durations),
+        None => (image_files, durations),
+    };
 
-    // Create a file list for FFmpeg concat demuxer with duration for slow-motion ending
-    let list_file_path = frames_dir.parent().unwrap().join("filelist.txt");
-    let mut list_content = String::new();
+    // Catch corrupt or truncated frames now instead of as an opaque FFmpeg exit code
+    let invalid_frames: Vec<String> = image_files
+        .iter()
+        .filter_map(|filename| {
+            validate_frame(&frames_dir.join(filename))
+                .err()
+                .map(|reason| format!("{} ({})", filename, reason))
+        })
+        .collect();
+    if !invalid_frames.is_empty() {
+        anyhow::bail!(
+            "{} frame(s) can't be decoded: {}",
+            invalid_frames.len(),
+            invalid_frames.iter().take(5).cloned().collect::<Vec<_>>().join(", ")
+        );
+    }
+
+    Ok((image_files, durations))
+}
+
+/// Async version of create_timelapse with real-time progress streaming.
+///
+/// Frames are selected, checked, deflickered and analysed for stabilization
+/// once, then encoded to each of `outputs` in turn.
+pub async fn create_timelapse_async(
+    job_id: &str,
+    frames_dir: PathBuf,
+    outputs: &[RenderOutput],
+    settings: &CreateTimelapseRequest,
+    job_store: JobStore,
+    cancel: CancellationToken,
+) -> Result<()> {
+    let mut progress = ProgressReporter {
+        job_store,
+        job_id: job_id.to_string(),
+        rendition: None,
+        completed_renditions: Vec::new(),
+    };
 
-    // Calculate durations
-    let normal_duration = 1.0 / fps as f64;
-    let slow_duration = 1.0 / SLOW_ENDING_FPS;
+    // Update status to preparing
+    progress.update("preparing", 0, 0, None);
+
+    let encodings = outputs
+        .iter()
+        .map(|output| Encoding::from_request(&output.settings))
+        .collect::<Result<Vec<_>, String>>()
+        .map_err(anyhow::Error::msg)?;
+
+    // Ordering, EXIF timestamps and decoding checks read every frame, so keep
+    // them off the async workers
+    let (image_files, durations) = {
+        let job_id = job_id.to_string();
+        let frames_dir = frames_dir.clone();
+        let settings = settings.clone();
+        tokio::task::spawn_blocking(move || prepare_frames(&job_id, &frames_dir, &settings))
+            .await
+            .context("Frame preparation task failed")??
+    };
 
-    // Determine which frames get slow-motion treatment
-    let slow_start_index = if image_files.len() > SLOW_ENDING_FRAMES {
-        image_files.len() - SLOW_ENDING_FRAMES
-    } else {
-        0 // If we have fewer frames than SLOW_ENDING_FRAMES, all get slow-mo
+    let total_frames = image_files.len() as u32;
+    let mut frame_paths: Vec<PathBuf> = image_files.iter().map(|f| frames_dir.join(f)).collect();
+
+    // Even out exposure changes between frames. Corrected copies go into a
+    // temporary directory that is removed when the render ends, however it ends
+    let _deflicker_dir = match &settings.deflicker {
+        Some(deflicker) => {
+            let dir = tempfile::Builder::new()
+                .prefix("deflicker")
+                .tempdir_in(frames_dir.parent().unwrap())
+                .context("Failed to create deflicker directory")?;
+
+            let out_dir = dir.path().to_path_buf();
+            let deflicker = deflicker.clone();
+            let cancel = cancel.clone();
+            let progress = progress.clone();
+            let sources = frame_paths.clone();
+            let steps = total_frames * 2;
+            let started = Instant::now();
+            progress.update("deflickering", 0, steps, None);
+
+            frame_paths = tokio::task::spawn_blocking(move || {
+                deflicker_frames(&sources, &out_dir, &deflicker, &cancel, |done| {
+                    let eta = estimate_remaining_seconds(started, done as u32, steps);
+                    progress.update("deflickering", done as u32, steps, eta);
+                })
+            })
+            .await
+            .context("Deflicker task failed")??;
+            Some(dir)
+        }
+        None => None,
     };
 
-    for (i, filename) in image_files.iter().enumerate() {
-        let file_path = frames_dir.join(filename);
+    // Create a file list for FFmpeg concat demuxer with per-frame durations
+    let list_file_path = frames_dir.parent().unwrap().join("filelist.txt");
+    let mut list_content = String::new();
+
+    let mut last_path = String::new();
+    for (file_path, duration) in frame_paths.into_iter().zip(&durations) {
         let abs_path = file_path.canonicalize().unwrap_or(file_path);
         let path_str = abs_path.to_string_lossy().replace('\'', "'\\''");
 
-        // Use slow duration for the last few frames
-        let duration = if i >= slow_start_index {
-            slow_duration
-        } else {
-            normal_duration
-        };
-
         list_content.push_str(&format!("file '{}'\n", path_str));
         list_content.push_str(&format!("duration {:.6}\n", duration));
+        last_path = path_str;
     }
+    // The concat demuxer ignores the last entry's duration unless the file is listed again
+    list_content.push_str(&format!("file '{}'\n", last_path));
     fs::write(&list_file_path, &list_content).context("Failed to create file list")?;
 
     // Check if FFmpeg exists before trying to use it
@@ -136,90 +357,110 @@ pub async fn create_timelapse_async(
         }
     }
 
-    // Build FFmpeg command using tokio async process
-    let mut cmd = TokioCommand::new("ffmpeg");
-
-    // Input settings using concat demuxer
-    // Note: We don't set -r before -i because we use duration directives in the file list
-    // for the slow-motion ending effect. The durations control frame display time.
-    cmd.arg("-f")
-        .arg("concat")
-        .arg("-safe")
-        .arg("0")
-        .arg("-i")
-        .arg(&list_file_path);
-
-    // Add rotation filter if needed
-    if let Some(filter) = rotation_filter {
-        cmd.arg("-vf").arg(filter);
+    // The job may have been cancelled while we were building the file list
+    if cancel.is_cancelled() {
+        let _ = fs::remove_file(&list_file_path);
+        anyhow::bail!("Render was cancelled");
     }
 
-    // Output settings - highest quality with fastest speed
-    cmd.arg("-c:v")
-        .arg("libx264")
-        .arg("-crf")
-        .arg("18")
-        .arg("-preset")
-        .arg("veryfast")
-        .arg("-threads")
-        .arg("0")
-        .arg("-pix_fmt")
-        .arg("yuv420p")
-        .arg("-movflags")
-        .arg("+faststart")
-        .arg("-progress")
-        .arg("pipe:2")  // Output progress to stderr
-        .arg("-y")
-        .arg(&output_path);
-
-    // Set up stderr capture for progress
-    cmd.stderr(Stdio::piped());
-    cmd.stdout(Stdio::null());
-
-    // Update to encoding stage
-    update_job_progress(&job_store, job_id, "encoding", 0, total_frames);
-
-    // Spawn the process
-    let mut child = cmd.spawn().context("Failed to spawn FFmpeg process")?;
-
-    // Read stderr for progress
-    let stderr = child.stderr.take().expect("Failed to capture stderr");
-    let reader = BufReader::new(stderr);
-    let mut lines = reader.lines();
-
-    let job_id_owned = job_id.to_string();
-    let job_store_clone = job_store.clone();
+    // Camera motion found by the stabilization analysis pass
+    let transforms_path = frames_dir.parent().unwrap().join("transforms.trf");
+
+    let result: Result<()> = async {
+        // Analyse camera shake on the cropped and rotated frames, so the encode
+        // pass sees exactly the frames the motion was measured on
+        let stabilization = match &settings.stabilize {
+            Some(stabilize) => {
+                let mut filters = source_filters(settings);
+                filters.push(stabilize.detect_filter(&transforms_path));
+
+                let mut cmd = concat_command(&list_file_path);
+                cmd.arg("-vf")
+                    .arg(filters.join(","))
+                    .arg("-progress")
+                    .arg("pipe:2")
+                    .arg("-f")
+                    .arg("null")
+                    .arg("-");
+                run_ffmpeg(cmd, &progress, "analyzing", total_frames, &cancel).await?;
+
+                Some(stabilize.transform_filter(&transforms_path))
+            }
+            None => None,
+        };
 
-    // Process stderr lines for progress updates
-    while let Ok(Some(line)) = lines.next_line().await {
-        if let Some(frame) = parse_frame_from_line(&line) {
-            update_job_progress(&job_store_clone, &job_id_owned, "encoding", frame, total_frames);
+        for (output, encoding) in outputs.iter().zip(&encodings) {
+            progress.rendition = output.rendition.clone();
+
+            // FFmpeg counts the frames it encodes, which interpolation multiplies
+            let encoded_frames = match &output.settings.interpolation {
+                Some(interpolation) => interpolation.output_frame_count(&durations),
+                None => total_frames,
+            };
+
+            let mut cmd = concat_command(&list_file_path);
+
+            // Add crop/rotation/stabilization/scale filters, and the GIF palette, if needed
+            let filters: Vec<String> = [
+                build_filter_chain(&output.settings, stabilization.clone()),
+                encoding.filter(),
+            ]
+            .into_iter()
+            .flatten()
+            .collect();
+            if !filters.is_empty() {
+                cmd.arg("-vf").arg(filters.join(","));
+            }
+
+            // Output settings for the requested codec and container
+            let partial_path = partial_output_path(&output.path);
+            cmd.args(encoding.ffmpeg_args())
+                .arg("-progress")
+                .arg("pipe:2")  // Output progress to stderr
+                .arg("-y")
+                .arg(&partial_path);
+
+            run_ffmpeg(cmd, &progress, "encoding", encoded_frames, &cancel).await?;
+
+            // Update to finalizing stage briefly
+            progress.update("finalizing", encoded_frames, encoded_frames, Some(0));
+
+            // Ensure output file is fully synced to disk before signaling completion
+            // This prevents race conditions where the file appears complete but data
+            // is still in the kernel write cache
+            let file = std::fs::File::open(&partial_path)
+                .context("Failed to open output file for sync")?;
+            file.sync_all()
+                .context("Failed to sync output file to disk")?;
+            drop(file);
+            fs::rename(&partial_path, &output.path)
+                .context("Failed to move output file into place")?;
+
+            progress.completed_renditions.extend(progress.rendition.take());
         }
-    }
 
-    // Wait for the process to complete
-    let status = child.wait().await.context("Failed to wait for FFmpeg")?;
+        Ok(())
+    }
+    .await;
 
-    // Clean up file list
+    // Clean up file list and motion data
     let _ = fs::remove_file(&list_file_path);
+    let _ = fs::remove_file(&transforms_path);
 
-    if !status.success() {
-        anyhow::bail!("FFmpeg failed with exit code {:?}", status.code());
+    if result.is_err() {
+        // Throw away the unfinished output, leaving the previous render's video in place
+        for output in outputs {
+            let _ = fs::remove_file(partial_output_path(&output.path));
+        }
     }
+    result
+}
 
-    // Update to finalizing stage briefly
-    update_job_progress(&job_store, job_id, "finalizing", total_frames, total_frames);
-
-    // Ensure output file is fully synced to disk before signaling completion
-    // This prevents race conditions where the file appears complete but data
-    // is still in the kernel write cache
-    let file = std::fs::File::open(&output_path)
-        .context("Failed to open output file for sync")?;
-    file.sync_all()
-        .context("Failed to sync output file to disk")?;
-    drop(file);
-
-    Ok(())
+/// Where an output is encoded before it replaces the previous one. The name
+/// keeps the extension, which FFmpeg picks the container from
+fn partial_output_path(path: &Path) -> PathBuf {
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!(".partial-{}", file_name))
 }
 
 #[allow(dead_code)]
@@ -0,0 +1,254 @@
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::Notify;
+use tokio_util::sync::CancellationToken;
+use crate::models::{CreateTimelapseRequest, JobStatusType};
+use crate::storage::job_store::JobStore;
+use crate::storage::local::{
+    get_draft_path, get_frames_directory, get_output_path, get_rendition_path, get_renditions_directory,
+};
+use crate::video::codec::{Container, Encoding};
+use crate::video::processor::{create_timelapse_async, RenderOutput};
+use crate::video::rendition::render_outputs;
+
+/// A render waiting for a free worker
+struct RenderTask {
+    job_id: String,
+    settings: CreateTimelapseRequest,
+}
+
+struct Inner {
+    pending: Mutex<VecDeque<RenderTask>>,
+    /// Cancellation handles of renders currently owned by a worker, with the
+    /// render's generation so a finished render never drops a newer one's handle
+    running: Mutex<HashMap<String, (u64, CancellationToken)>>,
+    next_generation: AtomicU64,
+    notify: Notify,
+    capacity: usize,
+}
+
+/// Returned when the queue already holds `capacity` waiting renders
+#[derive(Debug)]
+pub struct QueueFull;
+
+/// Where a cancelled render was when the cancellation arrived
+#[derive(Debug, PartialEq)]
+pub enum Cancellation {
+    /// Removed from the queue before a worker picked it up
+    Dequeued,
+    /// A worker was signalled to kill FFmpeg
+    Signalled,
+    /// The job is neither queued nor rendering
+    NotRendering,
+}
+
+/// FIFO render queue drained by a fixed pool of workers
+#[derive(Clone)]
+pub struct RenderQueue {
+    inner: Arc<Inner>,
+}
+
+impl RenderQueue {
+    /// Create the queue and spawn `workers` tasks that render jobs one at a time
+    pub fn start(workers: usize, capacity: usize, job_store: JobStore) -> Self {
+        let queue = Self {
+            inner: Arc::new(Inner {
+                pending: Mutex::new(VecDeque::new()),
+                running: Mutex::new(HashMap::new()),
+                next_generation: AtomicU64::new(0),
+                notify: Notify::new(),
+                capacity,
+            }),
+        };
+
+        for _ in 0..workers {
+            let queue = queue.clone();
+            let job_store = job_store.clone();
+            actix_web::rt::spawn(async move {
+                queue.run_worker(job_store).await;
+            });
+        }
+
+        queue
+    }
+
+    /// Add a render to the back of the queue, returning its 1-based position
+    pub fn enqueue(
+        &self,
+        job_id: &str,
+        settings: CreateTimelapseRequest,
+    ) -> Result<usize, QueueFull> {
+        let position = {
+            let mut pending = self.inner.pending.lock().unwrap();
+            if pending.len() >= self.inner.capacity {
+                return Err(QueueFull);
+            }
+            pending.push_back(RenderTask {
+                job_id: job_id.to_string(),
+                settings,
+            });
+            pending.len()
+        };
+
+        self.inner.notify.notify_one();
+        Ok(position)
+    }
+
+    /// 1-based position of a job that is still waiting for a worker
+    pub fn position(&self, job_id: &str) -> Option<usize> {
+        let pending = self.inner.pending.lock().unwrap();
+        pending
+            .iter()
+            .position(|task| task.job_id == job_id)
+            .map(|i| i + 1)
+    }
+
+    /// Stop a job, either by dropping it from the queue or by signalling its worker
+    pub fn cancel(&self, job_id: &str) -> Cancellation {
+        {
+            let mut pending = self.inner.pending.lock().unwrap();
+            if let Some(i) = pending.iter().position(|task| task.job_id == job_id) {
+                pending.remove(i);
+                return Cancellation::Dequeued;
+            }
+        }
+
+        match self.inner.running.lock().unwrap().get(job_id) {
+            Some((_, token)) => {
+                token.cancel();
+                Cancellation::Signalled
+            }
+            None => Cancellation::NotRendering,
+        }
+    }
+
+    async fn run_worker(&self, job_store: JobStore) {
+        loop {
+            // Pop and register under the pending lock so a job is always visible to cancel()
+            let task = {
+                let mut pending = self.inner.pending.lock().unwrap();
+                pending.pop_front().map(|task| {
+                    let token = CancellationToken::new();
+                    let generation = self.inner.next_generation.fetch_add(1, Ordering::Relaxed);
+                    self.inner.running.lock().unwrap()
+                        .insert(task.job_id.clone(), (generation, token.clone()));
+                    (task, generation, token)
+                })
+            };
+
+            match task {
+                Some((task, generation, token)) => {
+                    let job_id = task.job_id.clone();
+                    render(task, &job_store, token).await;
+                    // The job may have been rendered again as soon as its final
+                    // status was recorded
+                    let mut running = self.inner.running.lock().unwrap();
+                    if running.get(&job_id).is_some_and(|(g, _)| *g == generation) {
+                        running.remove(&job_id);
+                    }
+                }
+                None => self.inner.notify.notified().await,
+            }
+        }
+    }
+}
+
+/// Where each output of a render is written: `output.{ext}` for a single
+/// output, `renditions/{id}.{ext}` for each of several
+fn plan_outputs(job_id: &str, settings: &CreateTimelapseRequest) -> Result<Vec<RenderOutput>, String> {
+    render_outputs(settings)
+        .into_iter()
+        .map(|(rendition, settings)| {
+            let extension = Encoding::from_request(&settings)?.container.extension();
+            let path = match &rendition {
+                Some(id) => get_rendition_path(job_id, id, extension),
+                None if settings.draft.is_some() => get_draft_path(job_id),
+                None => get_output_path(job_id, extension),
+            };
+            Ok(RenderOutput { rendition, path, settings })
+        })
+        .collect()
+}
+
+/// Drop the outputs of earlier renders that this one didn't overwrite, like a
+/// different container or a rendition that is no longer requested
+fn remove_stale_outputs(job_id: &str, keep: &[PathBuf]) {
+    for container in Container::ALL {
+        let path = get_output_path(job_id, container.extension());
+        if !keep.contains(&path) {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+
+    let renditions_dir = get_renditions_directory(job_id);
+    let Ok(entries) = std::fs::read_dir(&renditions_dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        if !keep.contains(&entry.path()) {
+            let _ = std::fs::remove_file(entry.path());
+        }
+    }
+    // Only succeeds once no rendition is left
+    let _ = std::fs::remove_dir(renditions_dir);
+}
+
+/// Run a single render and record its outcome in the job store
+async fn render(task: RenderTask, job_store: &JobStore, cancel: CancellationToken) {
+    let job_id = task.job_id;
+    job_store.set_status(&job_id, JobStatusType::Processing(None));
+
+    // A draft is written next to the video and leaves it alone
+    let settings = match &task.settings.draft {
+        Some(draft) => draft.apply(&task.settings),
+        None => task.settings,
+    };
+
+    let frames_dir = get_frames_directory(&job_id);
+    let outputs = match plan_outputs(&job_id, &settings) {
+        Ok(outputs) => outputs,
+        Err(e) => {
+            job_store.set_status(&job_id, JobStatusType::Failed(e));
+            return;
+        }
+    };
+    if settings.renditions.is_some() {
+        if let Err(e) = std::fs::create_dir_all(get_renditions_directory(&job_id)) {
+            job_store.set_status(&job_id, JobStatusType::Failed(e.to_string()));
+            return;
+        }
+    }
+
+    // A cancellation that lands after FFmpeg exits still wins over the result
+    match create_timelapse_async(
+        &job_id,
+        frames_dir,
+        &outputs,
+        &settings,
+        job_store.clone(),
+        cancel.clone(),
+    ).await {
+        _ if cancel.is_cancelled() => {
+            job_store.set_status(&job_id, JobStatusType::Cancelled);
+        }
+        Ok(_) => {
+            if settings.draft.is_none() {
+                let written: Vec<PathBuf> = outputs.into_iter().map(|output| output.path).collect();
+                remove_stale_outputs(&job_id, &written);
+            }
+            job_store.set_status(&job_id, JobStatusType::Completed);
+        }
+        Err(e) => {
+            let error_msg = format!("{}", e);
+            // Truncate error message if too long for UI
+            let display_error = if error_msg.len() > 500 {
+                format!("{}...", &error_msg[..500])
+            } else {
+                error_msg
+            };
+            job_store.set_status(&job_id, JobStatusType::Failed(display_error));
+        }
+    }
+}
@@ -0,0 +1,157 @@
+use std::collections::HashSet;
+use serde::{Deserialize, Serialize};
+use crate::models::CreateTimelapseRequest;
+use crate::video::codec::{Container, Encoding, VideoCodec};
+use crate::video::filters::{validate_geometry, FitMode, ResolutionPreset};
+use crate::video::interpolate::Interpolation;
+use crate::video::pacing::MAX_FPS;
+
+/// Most outputs one render request can produce
+const MAX_RENDITIONS: usize = 8;
+
+/// Longest rendition id
+const MAX_ID_LENGTH: usize = 32;
+
+/// One output of a render that produces several, e.g. a 4K master next to a
+/// 1080p web version. Unset fields fall back to the render request's.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Rendition {
+    /// Name of the rendition in status responses and its download URL
+    pub id: String,
+    /// Replaces the request's codec along with its container, quality, preset
+    /// and loop count, which may not suit another codec
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub codec: Option<VideoCodec>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub container: Option<Container>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quality: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preset: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub loop_count: Option<u32>,
+    /// Replaces the request's output size along with `width` and `height`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resolution: Option<ResolutionPreset>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub width: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub height: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fit: Option<FitMode>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interpolation: Option<Interpolation>,
+}
+
+impl Rendition {
+    /// The request's settings with this rendition's output settings applied
+    fn apply(&self, base: &CreateTimelapseRequest) -> CreateTimelapseRequest {
+        let mut settings = base.clone();
+        settings.renditions = None;
+
+        if let Some(codec) = self.codec {
+            settings.codec = codec;
+            settings.container = self.container;
+            settings.quality = self.quality;
+            settings.preset = self.preset.clone();
+            settings.loop_count = self.loop_count;
+        } else {
+            settings.container = self.container.or(base.container);
+            settings.quality = self.quality.or(base.quality);
+            settings.preset = self.preset.clone().or_else(|| base.preset.clone());
+            settings.loop_count = self.loop_count.or(base.loop_count);
+        }
+
+        if self.resolution.is_some() || self.width.is_some() || self.height.is_some() {
+            settings.resolution = self.resolution;
+            settings.width = self.width;
+            settings.height = self.height;
+        }
+        if let Some(fit) = self.fit {
+            settings.fit = fit;
+        }
+        if self.interpolation.is_some() {
+            settings.interpolation = self.interpolation.clone();
+        }
+
+        settings
+    }
+}
+
+/// Rendition ids end up in file names and URLs
+pub fn is_valid_rendition_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_ID_LENGTH
+        && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Settings of every output of a render, with the rendition id for renders
+/// that produce several. A render without renditions has a single output with
+/// the request's own settings.
+pub fn render_outputs(req: &CreateTimelapseRequest) -> Vec<(Option<String>, CreateTimelapseRequest)> {
+    match &req.renditions {
+        Some(renditions) => renditions
+            .iter()
+            .map(|rendition| (Some(rendition.id.clone()), rendition.apply(req)))
+            .collect(),
+        None => vec![(None, req.clone())],
+    }
+}
+
+/// Highest frame rate a render can play at. Every output plays at the same
+/// rate, so the strictest codec sets the limit.
+pub fn max_output_fps(req: &CreateTimelapseRequest) -> f64 {
+    render_outputs(req)
+        .iter()
+        .map(|(_, settings)| settings.codec.max_fps())
+        .fold(MAX_FPS, f64::min)
+}
+
+/// Check that every output's interpolation raises the frame rate above the
+/// request's `fps`; anything lower would drop frames instead of adding them
+pub fn validate_interpolation_fps(req: &CreateTimelapseRequest) -> Result<(), String> {
+    for (_, settings) in render_outputs(req) {
+        if let Some(interpolation) = &settings.interpolation {
+            if interpolation.fps <= req.fps {
+                return Err(format!(
+                    "Interpolation fps must be above the video's {} fps",
+                    req.fps
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Check the rendition list and each rendition's output settings
+pub fn validate_renditions(req: &CreateTimelapseRequest) -> Result<(), String> {
+    let Some(renditions) = &req.renditions else {
+        return Ok(());
+    };
+
+    if renditions.is_empty() || renditions.len() > MAX_RENDITIONS {
+        return Err(format!("Give between 1 and {} renditions", MAX_RENDITIONS));
+    }
+
+    let mut ids = HashSet::new();
+    for rendition in renditions {
+        if !is_valid_rendition_id(&rendition.id) {
+            return Err(format!(
+                "Rendition ids must be 1 to {} letters, digits, '-' or '_'",
+                MAX_ID_LENGTH
+            ));
+        }
+        if !ids.insert(rendition.id.as_str()) {
+            return Err(format!("Duplicate rendition id: {}", rendition.id));
+        }
+
+        let settings = rendition.apply(req);
+        Encoding::from_request(&settings)
+            .and_then(|_| validate_geometry(&settings))
+            .and_then(|_| settings.interpolation.as_ref().map_or(Ok(()), |i| i.validate()))
+            .map_err(|e| format!("Rendition {}: {}", rendition.id, e))?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,58 @@
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+
+/// Longest smoothing window, in frames either side of the current one
+const MAX_SMOOTHING: u32 = 100;
+
+fn default_smoothing() -> u32 {
+    10
+}
+
+fn default_shakiness() -> u32 {
+    5
+}
+
+/// Two-pass video stabilization settings of a render request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Stabilize {
+    /// Frames either side of each frame the camera path is smoothed over;
+    /// higher values give a steadier but slower-reacting picture, 1 to 100
+    #[serde(default = "default_smoothing")]
+    pub smoothing: u32,
+    /// How shaky the footage is, 1 (a little) to 10 (a lot)
+    #[serde(default = "default_shakiness")]
+    pub shakiness: u32,
+}
+
+impl Stabilize {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(1..=MAX_SMOOTHING).contains(&self.smoothing) {
+            return Err(format!("Stabilize smoothing must be between 1 and {}", MAX_SMOOTHING));
+        }
+        if !(1..=10).contains(&self.shakiness) {
+            return Err("Stabilize shakiness must be between 1 and 10".to_string());
+        }
+        Ok(())
+    }
+
+    /// Filter for the analysis pass, writing the camera motion to `transforms`
+    pub fn detect_filter(&self, transforms: &Path) -> String {
+        format!(
+            "vidstabdetect=shakiness={}:accuracy=15:result='{}'",
+            self.shakiness,
+            transforms.display()
+        )
+    }
+
+    /// Filters for the encode pass: undo the motion in `transforms`, zooming
+    /// just enough to hide the moving borders, then sharpen away the softness
+    /// interpolation leaves behind
+    pub fn transform_filter(&self, transforms: &Path) -> String {
+        format!(
+            "vidstabtransform=input='{}':smoothing={}:optzoom=1:interpol=bicubic,unsharp=5:5:0.8:3:3:0.4",
+            transforms.display(),
+            self.smoothing
+        )
+    }
+}
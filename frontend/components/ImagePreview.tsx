@@ -15,7 +15,8 @@ export default function ImagePreview({ jobId, fileCount, rotation }: ImagePrevie
 
   // Compute the expected URL (null if no valid job)
   const expectedUrl = jobId && fileCount > 0
-    ? getPreviewUrl(jobId, Math.floor(fileCount / 2))
+    // Large enough for the preview pane on high-density screens, far smaller than a DSLR frame
+    ? getPreviewUrl(jobId, Math.floor(fileCount / 2), undefined, { width: 1600, height: 1600 })
     : null;
 
   // Derive loading state from comparison
@@ -11,6 +11,7 @@ interface VideoPlayerProps {
 
 export default function VideoPlayer({ jobId, cacheBuster, onAdjust, onReset }: VideoPlayerProps) {
   const videoUrl = getDownloadUrl(jobId, cacheBuster ?? undefined);
+  const downloadUrl = getDownloadUrl(jobId, cacheBuster ?? undefined, 'attachment');
 
   return (
     <div className="w-full bg-cream-light border border-cream-dark rounded-2xl p-4 sm:p-6 md:p-8">
@@ -42,8 +43,10 @@ export default function VideoPlayer({ jobId, cacheBuster, onAdjust, onReset }: V
               Adjust & Regenerate
             </button>
             <a
-              href={videoUrl}
-              download={`timelapse_${jobId}.mp4`}
+              href={downloadUrl}
+              // The server's Content-Disposition makes this a download and names
+              // the file with the right extension, even cross-origin
+              download
               className="flex-1 inline-flex items-center justify-center gap-2 px-4 sm:px-6 py-2.5 sm:py-3 bg-charcoal text-cream rounded-full hover:bg-charcoal-light transition-colors font-medium text-sm sm:text-base"
             >
               <svg
@@ -4,28 +4,186 @@ export interface UploadResponse {
   jobId: string;
   fileCount: number;
   filenames: string[];
+  collisions: string[];
+  rejected: RejectedFile[];
+}
+
+export interface RejectedFile {
+  filename: string;
+  reason: string;
 }
 
 export type Rotation = 0 | 90 | 180 | 270;
 
+export type FrameOrder = 'filename' | 'exif' | 'mtime' | 'explicit';
+
+export type VideoCodec = 'h264' | 'h265' | 'vp9' | 'av1' | 'prores' | 'gif' | 'webp';
+
+export type VideoContainer = 'mp4' | 'webm' | 'mov' | 'gif' | 'webp';
+
+export type ResolutionPreset = '720p' | '1080p' | '1440p' | '4k';
+
+export type FitMode = 'letterbox' | 'crop' | 'stretch';
+
+/** Source region in pixels of the unrotated frame; width and height must be even */
+export interface CropRect {
+  x: number;
+  y: number;
+  width: number;
+  height: number;
+}
+
+export interface SlowMotion {
+  /** Number of frames slowed down; 0 disables the effect */
+  frames: number;
+  /** Defaults to 2 */
+  fps?: number;
+}
+
+export type Weekday = 'mon' | 'tue' | 'wed' | 'thu' | 'fri' | 'sat' | 'sun';
+
+export interface FrameSelection {
+  /** Defaults to 'exif', falling back to the file time */
+  timestamps?: 'exif' | 'mtime';
+  /** First day to include, YYYY-MM-DD */
+  from?: string;
+  /** Last day to include, YYYY-MM-DD */
+  to?: string;
+  weekdays?: Weekday[];
+  /** Times of day as HH:MM; start after end wraps past midnight */
+  timeWindows?: { start: string; end: string }[];
+  /** At most 31622400 (366 days) */
+  minIntervalSeconds?: number;
+  everyNth?: number;
+}
+
+export interface Deflicker {
+  /** 0 to 1, defaults to 1 */
+  strength?: number;
+  /** Odd number of frames to average over, 3 to 101, defaults to 15 */
+  window?: number;
+}
+
+export interface Stabilize {
+  /** Frames either side the camera path is smoothed over, 1 to 100, defaults to 10 */
+  smoothing?: number;
+  /** 1 (a little) to 10 (a lot), defaults to 5 */
+  shakiness?: number;
+}
+
+export type InterpolationMode = 'motion' | 'blend';
+
+export interface Interpolation {
+  /** Output frame rate, 1 to 120 and above the request's fps, defaults to 60 */
+  fps?: number;
+  /** Defaults to 'motion' */
+  mode?: InterpolationMode;
+}
+
+/** Quick low-quality render for checking pacing */
+export interface Draft {
+  /** Encode every Nth frame only, 1 to 100, defaults to 1; the length stays the same */
+  frameStep?: number;
+}
+
+/** One output of a render that produces several; unset fields come from the request */
+export interface Rendition {
+  /** Letters, digits, '-' or '_', unique within the request */
+  id: string;
+  /** Also replaces the request's container, quality, preset and loopCount */
+  codec?: VideoCodec;
+  container?: VideoContainer;
+  quality?: number;
+  preset?: string;
+  loopCount?: number;
+  /** Setting any size field replaces the request's size */
+  resolution?: ResolutionPreset;
+  width?: number;
+  height?: number;
+  fit?: FitMode;
+  interpolation?: Interpolation;
+}
+
 export interface CreateTimelapseRequest {
   jobId: string;
   rotation: Rotation;
-  fps: number;
+  /** Required unless targetDurationSeconds is given */
+  fps?: number;
+  /** Desired video length; the backend works out fps (at least 1) and frameStep */
+  targetDurationSeconds?: number;
+  /** Only every Nth frame is rendered; must be left out with targetDurationSeconds */
+  frameStep?: number;
+  order?: FrameOrder;
+  selection?: FrameSelection;
+  /** Frame filenames in render order, required when order is 'explicit' */
+  explicitOrder?: string[];
+  codec?: VideoCodec;
+  /** Defaults to mp4 for h264/h265, webm for vp9/av1, mov for prores and the codec's own format for gif/webp */
+  container?: VideoContainer;
+  /** CRF value, or 0-100 quality for webp; not used by prores or gif */
+  quality?: number;
+  /** Encoder speed preset, the profile for prores or the dither for gif */
+  preset?: string;
+  /** Output size preset; mutually exclusive with width/height */
+  resolution?: ResolutionPreset;
+  width?: number;
+  height?: number;
+  fit?: FitMode;
+  /** Source region to keep, in pixels of the unrotated frame */
+  crop?: CropRect;
+  /** Off by default */
+  slowStart?: SlowMotion;
+  /** The last 5 frames at 2 fps by default */
+  slowEnding?: SlowMotion;
+  /** Extra seconds the last frame stays on screen */
+  holdLastSeconds?: number;
+  /** Even out brightness changes between frames */
+  deflicker?: Deflicker;
+  /** Remove camera shake with a motion analysis pass */
+  stabilize?: Stabilize;
+  /** Synthesize in-between frames for smoother playback */
+  interpolation?: Interpolation;
+  /** Times a gif or webp animation plays; 0 or unset loops forever */
+  loopCount?: number;
+  /** Produce up to 8 outputs instead of one */
+  renditions?: Rendition[];
+  /** Render a small, fast draft into its own file instead of the video */
+  draft?: Draft;
 }
 
 export interface CreateTimelapseResponse {
   jobId: string;
-  status: 'pending' | 'processing';
+  status: 'queued';
+  queuePosition?: number;
+  fps: number;
+  frameStep: number;
+  frameCount: number;
+  durationSeconds: number;
 }
 
 export interface JobStatus {
-  status: 'pending' | 'processing' | 'completed' | 'failed';
+  status: 'pending' | 'queued' | 'processing' | 'completed' | 'failed' | 'cancelled';
+  queuePosition?: number;
   progress?: number;
-  stage?: 'preparing' | 'encoding' | 'finalizing' | 'complete';
+  stage?: 'preparing' | 'deflickering' | 'analyzing' | 'encoding' | 'finalizing' | 'complete';
   currentFrame?: number;
   totalFrames?: number;
+  /** Estimated seconds until encoding finishes */
+  etaSeconds?: number;
   error?: string;
+  /** Per-output status of a render with renditions */
+  renditions?: RenditionStatus[];
+  /** Set once a draft render has finished, relative to the API URL */
+  draftUrl?: string;
+}
+
+export interface RenditionStatus {
+  id: string;
+  status: 'pending' | 'processing' | 'completed' | 'failed' | 'cancelled';
+  progress: number;
+  etaSeconds?: number;
+  /** Path under the API, set once the rendition has been written */
+  downloadUrl?: string;
 }
 
 export async function uploadFiles(
@@ -86,8 +244,77 @@ export async function uploadFiles(
   });
 }
 
-export function getPreviewUrl(jobId: string, index: number): string {
-  return `${API_URL}/api/preview/${jobId}/${index}`;
+export type ThumbnailFormat = 'jpeg' | 'png' | 'webp';
+
+/** Bounding box (1 to 2048 per side) and format of a preview thumbnail */
+export interface ThumbnailOptions {
+  width?: number;
+  height?: number;
+  format?: ThumbnailFormat;
+}
+
+export function getPreviewUrl(
+  jobId: string,
+  index: number,
+  order?: Exclude<FrameOrder, 'explicit'>,
+  thumbnail?: ThumbnailOptions
+): string {
+  const params = new URLSearchParams();
+  if (order) params.set('order', order);
+  if (thumbnail?.width) params.set('width', String(thumbnail.width));
+  if (thumbnail?.height) params.set('height', String(thumbnail.height));
+  if (thumbnail?.format) params.set('format', thumbnail.format);
+  const query = params.toString();
+  return `${API_URL}/api/preview/${jobId}/${index}${query ? `?${query}` : ''}`;
+}
+
+/** Grid of a contact sheet; defaults to 6x6 tiles 240 pixels wide */
+export interface ContactSheetOptions {
+  /** 1 to 20 */
+  columns?: number;
+  /** 1 to 20 */
+  rows?: number;
+  /** 32 to 1024; columns or rows times the tile width may be at most 8192 */
+  tileWidth?: number;
+  format?: ThumbnailFormat;
+  order?: Exclude<FrameOrder, 'explicit'>;
+}
+
+export function getContactSheetUrl(jobId: string, options: ContactSheetOptions = {}): string {
+  const params = new URLSearchParams();
+  if (options.columns) params.set('columns', String(options.columns));
+  if (options.rows) params.set('rows', String(options.rows));
+  if (options.tileWidth) params.set('tileWidth', String(options.tileWidth));
+  if (options.format) params.set('format', options.format);
+  if (options.order) params.set('order', options.order);
+  const query = params.toString();
+  return `${API_URL}/api/jobs/${jobId}/contact-sheet${query ? `?${query}` : ''}`;
+}
+
+/**
+ * Render the frame at `index` with the settings of a render request, exactly
+ * as it will appear in the video. Resolves to an object URL of the PNG; revoke
+ * it with `URL.revokeObjectURL` when done.
+ */
+export async function renderPreview(
+  request: CreateTimelapseRequest,
+  index: number,
+  renditionId?: string
+): Promise<string> {
+  const query = renditionId ? `?rendition=${encodeURIComponent(renditionId)}` : '';
+  const response = await fetch(`${API_URL}/api/preview/${request.jobId}/${index}${query}`, {
+    method: 'POST',
+    headers: {
+      'Content-Type': 'application/json',
+    },
+    body: JSON.stringify(request),
+  });
+
+  if (!response.ok) {
+    throw new Error(`Failed to render preview: ${response.statusText}`);
+  }
+
+  return URL.createObjectURL(await response.blob());
 }
 
 export async function createTimelapse(
@@ -118,7 +345,74 @@ export async function getJobStatus(jobId: string): Promise<JobStatus> {
   return response.json();
 }
 
-export function getDownloadUrl(jobId: string, cacheBuster?: number): string {
-  const url = `${API_URL}/api/download/${jobId}`;
-  return cacheBuster ? `${url}?t=${cacheBuster}` : url;
+/**
+ * Follow a job's status via Server-Sent Events. The stream closes by itself
+ * once the job completes, fails or is cancelled; call the returned function to
+ * stop listening earlier.
+ */
+export function subscribeToJobEvents(
+  jobId: string,
+  onStatus: (status: JobStatus) => void,
+  onError?: () => void
+): () => void {
+  const source = new EventSource(`${API_URL}/api/jobs/${jobId}/events`);
+  const finalStatuses = ['completed', 'failed', 'cancelled'];
+
+  const handle = (event: MessageEvent) => {
+    const status: JobStatus = JSON.parse(event.data);
+    onStatus(status);
+    if (finalStatuses.includes(status.status)) {
+      source.close();
+    }
+  };
+
+  ['pending', 'queued', 'processing', ...finalStatuses].forEach((name) => {
+    source.addEventListener(name, handle as EventListener);
+  });
+  source.onerror = () => {
+    // EventSource reconnects on its own unless the stream is closed
+    if (source.readyState === EventSource.CLOSED && onError) {
+      onError();
+    }
+  };
+
+  return () => source.close();
+}
+
+export async function cancelRender(jobId: string): Promise<void> {
+  const response = await fetch(`${API_URL}/api/jobs/${jobId}/render`, {
+    method: 'DELETE',
+  });
+
+  if (!response.ok) {
+    throw new Error(`Failed to cancel render: ${response.statusText}`);
+  }
+}
+
+/** 'inline' plays the video in the page, 'attachment' makes the browser save it */
+export type Disposition = 'inline' | 'attachment';
+
+function withDownloadParams(url: string, cacheBuster?: number, disposition?: Disposition): string {
+  const params = new URLSearchParams();
+  if (cacheBuster) params.set('t', String(cacheBuster));
+  if (disposition) params.set('disposition', disposition);
+  const query = params.toString();
+  return query ? `${url}?${query}` : url;
+}
+
+export function getDownloadUrl(jobId: string, cacheBuster?: number, disposition?: Disposition): string {
+  return withDownloadParams(`${API_URL}/api/download/${jobId}`, cacheBuster, disposition);
+}
+
+export function getRenditionDownloadUrl(
+  jobId: string,
+  renditionId: string,
+  cacheBuster?: number,
+  disposition?: Disposition
+): string {
+  return withDownloadParams(`${API_URL}/api/download/${jobId}/${renditionId}`, cacheBuster, disposition);
+}
+
+export function getDraftDownloadUrl(jobId: string, cacheBuster?: number, disposition?: Disposition): string {
+  return withDownloadParams(`${API_URL}/api/draft/${jobId}`, cacheBuster, disposition);
 }